@@ -346,6 +346,19 @@ fn snake_case_helper(
     Ok(())
 }
 
+fn lookup_name_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    let param = h.param(0).unwrap().value().as_str().unwrap();
+    let name = param.replace(" ", "").replace(".", "").replace("_", "").to_lowercase();
+    out.write(&name)?;
+    Ok(())
+}
+
 fn pascal_case_helper(
     h: &Helper,
     _: &Handlebars,
@@ -397,6 +410,32 @@ pub enum HapType {
 }
 
 impl HapType {
+    /// Looks up a `HapType` by its name, e.g. `\"Current Temperature\"` or `\"CurrentTemperature\"`, or by its full
+    /// or shortened UUID, e.g. `\"00000011-0000-1000-8000-0026BB765291\"` or `\"11\"`.
+    pub fn from_name_or_uuid(name_or_uuid: &str) -> Option<HapType> {
+        let name = name_or_uuid.replace(\" \", \"\").replace(\".\", \"\").replace(\"_\", \"\").to_lowercase();
+        match name.as_str() {
+{{#each Characteristics as |c|}}\
+\t\t\t\"{{lookup_name c.Name}}\" => return Some(HapType::{{pascal_case c.Name}}),
+{{/each}}\
+{{#each Services as |s|}}\
+\t\t\t\"{{lookup_name s.Name}}\" => return Some(HapType::{{pascal_case s.Name}}),
+{{/each}}\
+\t\t\t_ => {},
+        }
+
+        let uuid = name_or_uuid.split('-').next().unwrap_or_default().trim_start_matches('0').to_uppercase();
+        match uuid.as_str() {
+{{#each Characteristics as |c|}}\
+\t\t\t\"{{uuid c.UUID}}\" => Some(HapType::{{pascal_case c.Name}}),
+{{/each}}\
+{{#each Services as |s|}}\
+\t\t\t\"{{uuid s.UUID}}\" => Some(HapType::{{pascal_case s.Name}}),
+{{/each}}\
+\t\t\t_ => None,
+        }
+    }
+
     /// Converts a `HapType` to its corresponding shortened UUID string.
     pub(crate) fn to_string(self) -> String {
         match self {
@@ -430,18 +469,24 @@ use serde_json::json;
 use crate::{
     characteristic::{
         AsyncCharacteristicCallbacks,
+        CallbackContext,
+        ChangeOrigin,
         Characteristic,
         CharacteristicCallbacks,
+        ContextCharacteristicCallbacks,
         Format,
         HapCharacteristic,
         HapCharacteristicSetup,
         HapType,
+        OnReadContextFuture,
         OnReadFn,
         OnReadFuture,
+        OnUpdateContextFuture,
         OnUpdateFn,
         OnUpdateFuture,
         Perm,
         Unit,
+        ValueStream,
     },
     pointer,
     Error,
@@ -472,30 +517,62 @@ impl {{pascal_case characteristic.Name}}Characteristic {
             ..Default::default()
         })
     }
+
+    /// Returns the value of the {{characteristic.Name}} Characteristic.
+    pub async fn get_value(&mut self) -> Result<{{type characteristic.Format}}> { self.0.get_value().await }
+
+    /// Sets the value of the {{characteristic.Name}} Characteristic.
+    pub async fn set_value(&mut self, value: {{type characteristic.Format}}) -> Result<()> { self.0.set_value(value).await }
+
+    /// Returns a stream yielding every change to the value of the {{characteristic.Name}} Characteristic.
+    pub fn watch(&mut self) -> ValueStream<{{type characteristic.Format}}> { self.0.watch() }
 }
 
 #[async_trait]
 impl HapCharacteristic for {{pascal_case characteristic.Name}}Characteristic {
     fn get_id(&self) -> u64 { self.0.get_id() }
 
+    fn set_id(&mut self, id: u64) { self.0.set_id(id) }
+
+    fn set_accessory_id(&mut self, accessory_id: u64) { self.0.set_accessory_id(accessory_id) }
+
     fn get_type(&self) -> HapType { self.0.get_type() }
 
     fn get_format(&self) -> Format { self.0.get_format() }
 
     fn get_perms(&self) -> Vec<Perm> { self.0.get_perms() }
 
+    fn set_description(&mut self, description: Option<String>) { self.0.set_description(description) }
+
     fn get_event_notifications(&self) -> Option<bool> { self.0.get_event_notifications() }
 
     fn set_event_notifications(&mut self, event_notifications: Option<bool>) {
         self.0.set_event_notifications(event_notifications)
     }
 
+    fn is_persistent(&self) -> bool { self.0.is_persistent() }
+
+    fn set_persistent(&mut self, persistent: bool) { self.0.set_persistent(persistent) }
+
     async fn get_value(&mut self) -> Result<serde_json::Value> {
         let value = self.0.get_value().await?;
         Ok(json!(value))
     }
 
     async fn set_value(&mut self, value: serde_json::Value) -> Result<()> {
+        self.set_value_with_origin(value, ChangeOrigin::Application).await
+    }
+
+    async fn set_value_with_origin(&mut self, value: serde_json::Value, origin: ChangeOrigin) -> Result<()> {
+        self.set_value_with_context(value, &CallbackContext::with_origin(origin)).await
+    }
+
+    async fn get_value_with_context(&mut self, context: &CallbackContext) -> Result<serde_json::Value> {
+        let value = self.0.get_value_with_context(context).await?;
+        Ok(json!(value))
+    }
+
+    async fn set_value_with_context(&mut self, value: serde_json::Value, context: &CallbackContext) -> Result<()> {
         let v;
         // for whatever reason, the controller is setting boolean values either as a boolean or as an integer
         if self.0.format == Format::Bool && value.is_number() {
@@ -510,17 +587,41 @@ impl HapCharacteristic for {{pascal_case characteristic.Name}}Characteristic {
         } else {
             v = serde_json::from_value(value).map_err(|_| Error::InvalidValue(self.get_format()))?;
         }
-        self.0.set_value(v).await
+        self.0.set_value_with_context(v, context).await
+    }
+
+    fn restore_value(&mut self, value: serde_json::Value) -> Result<()> {
+        let v = serde_json::from_value(value).map_err(|_| Error::InvalidValue(self.get_format()))?;
+        self.0.restore_value(v);
+        Ok(())
     }
 
     fn get_unit(&self) -> Option<Unit> { self.0.get_unit() }
 
     fn get_max_value(&self) -> Option<serde_json::Value> { self.0.get_max_value().map(|v| json!(v)) }
 
+    fn set_max_value(&mut self, max_value: Option<serde_json::Value>) -> Result<()> {
+        let v = max_value.map(serde_json::from_value).transpose().map_err(|_| Error::InvalidValue(self.get_format()))?;
+        self.0.set_max_value(v);
+        Ok(())
+    }
+
     fn get_min_value(&self) -> Option<serde_json::Value> { self.0.get_min_value().map(|v| json!(v)) }
 
+    fn set_min_value(&mut self, min_value: Option<serde_json::Value>) -> Result<()> {
+        let v = min_value.map(serde_json::from_value).transpose().map_err(|_| Error::InvalidValue(self.get_format()))?;
+        self.0.set_min_value(v);
+        Ok(())
+    }
+
     fn get_step_value(&self) -> Option<serde_json::Value> { self.0.get_step_value().map(|v| json!(v)) }
 
+    fn set_step_value(&mut self, step_value: Option<serde_json::Value>) -> Result<()> {
+        let v = step_value.map(serde_json::from_value).transpose().map_err(|_| Error::InvalidValue(self.get_format()))?;
+        self.0.set_step_value(v);
+        Ok(())
+    }
+
     fn get_max_len(&self) -> Option<u16> { self.0.get_max_len() }
 }
 
@@ -541,10 +642,28 @@ impl AsyncCharacteristicCallbacks<{{type characteristic.Format}}> for {{pascal_c
 
     fn on_update_async(&mut self, f: Option<impl OnUpdateFuture<{{type characteristic.Format}}>>) { self.0.on_update_async(f) }
 }
+
+impl ContextCharacteristicCallbacks<{{type characteristic.Format}}> for {{pascal_case characteristic.Name}}Characteristic {
+    fn on_read_with_context(&mut self, f: Option<impl OnReadContextFuture<{{type characteristic.Format}}>>) { self.0.on_read_with_context(f) }
+
+    fn on_update_with_context(&mut self, f: Option<impl OnUpdateContextFuture<{{type characteristic.Format}}>>) { self.0.on_update_with_context(f) }
+}
 ";
 
 static CHARACTERISTIC_MOD: &'static str = "// THIS FILE IS AUTO-GENERATED
 {{#each characteristics as |c|}}\npub mod {{c}};{{/each}}
+
+use crate::{characteristic::HapCharacteristic, HapType};
+
+/// Creates a new Characteristic of the given `HapType`. Returns `None` if the `HapType` isn't a Characteristic type.
+pub(crate) fn new_characteristic(hap_type: HapType, id: u64, accessory_id: u64) -> Option<Box<dyn HapCharacteristic>> {
+    match hap_type {
+{{#each definitions as |c|}}\
+\t\tHapType::{{pascal_case c.Name}} => Some(Box::new({{snake_case c.Name}}::{{pascal_case c.Name}}Characteristic::new(id, accessory_id))),
+{{/each}}\
+\t\t_ => None,
+    }
+}
 ";
 
 static SERVICE: &'static str = "// THIS FILE IS AUTO-GENERATED\n
@@ -553,6 +672,7 @@ use serde::ser::{Serialize, SerializeStruct, Serializer};
 use crate::{
     service::HapService,
     characteristic::{
+        CharacteristicOverrides,
         HapCharacteristic,
 {{#each required_characteristics as |r|}}\
 \t\t{{snake_case r.Name}}::{{pascal_case r.Name}}Characteristic,
@@ -575,6 +695,8 @@ pub struct {{pascal_case service.Name}}Service {
     hidden: bool,
     /// Specifies if the Service is the primary Service of the Accessory.
     primary: bool,
+    /// IDs of the Services linked to the Service.
+    linked_services: Vec<u64>,
 
 {{#each required_characteristics as |r|}}\
 \t/// {{r.Name}} Characteristic (required).
@@ -598,6 +720,54 @@ impl {{pascal_case service.Name}}Service {
         \t\t\t..Default::default()
         }
     }
+
+    /// Returns a builder for a {{service.Name}} Service with optional Characteristics enabled.
+    pub fn builder() -> {{pascal_case service.Name}}ServiceBuilder {
+        {{pascal_case service.Name}}ServiceBuilder::default()
+    }
+}
+
+/// Builder for a {{service.Name}} Service, enabling optional Characteristics and overriding Characteristic metadata.
+#[derive(Debug, Default)]
+pub struct {{pascal_case service.Name}}ServiceBuilder {
+    optional_characteristics: Vec<HapType>,
+    overrides: Vec<(HapType, CharacteristicOverrides)>,
+}
+
+impl {{pascal_case service.Name}}ServiceBuilder {
+{{#each optional_characteristics as |r|}}\
+\t/// Enables the {{r.Name}} Characteristic.
+\tpub fn with_{{snake_case r.Name}}(mut self) -> Self {
+\t\tself.optional_characteristics.push(HapType::{{pascal_case r.Name}});
+\t\tself
+\t}
+
+{{/each}}\
+\t/// Overrides metadata of a required or enabled optional Characteristic.
+    pub fn with_overrides(mut self, hap_type: HapType, overrides: CharacteristicOverrides) -> Self {
+        self.overrides.push((hap_type, overrides));
+        self
+    }
+
+    /// Builds the {{service.Name}} Service. The enabled optional Characteristics are given consecutive IDs following
+    /// the ones of the required Characteristics.
+    pub fn build(self, id: u64, accessory_id: u64) -> crate::Result<{{pascal_case service.Name}}Service> {
+        let mut service = {{pascal_case service.Name}}Service::new(id, accessory_id);
+{{#each optional_characteristics as |r|}}\
+\t\tif self.optional_characteristics.contains(&HapType::{{pascal_case r.Name}}) {
+\t\t\tlet next_id = id + 1 + service.get_characteristics().len() as u64;
+\t\t\tservice.{{snake_case r.Name}} = Some({{pascal_case r.Name}}Characteristic::new(next_id, accessory_id));
+\t\t}
+{{/each}}\
+\t\tfor (hap_type, overrides) in &self.overrides {
+            let characteristic = service
+                .get_mut_characteristic(*hap_type)
+                .ok_or(crate::Error::UnsupportedCharacteristic(HapType::{{pascal_case service.Name}}, *hap_type))?;
+            overrides.apply(characteristic)?;
+        }
+
+        Ok(service)
+    }
 }
 
 impl HapService for {{pascal_case service.Name}}Service {
@@ -605,6 +775,10 @@ impl HapService for {{pascal_case service.Name}}Service {
         self.id
     }
 
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
     fn get_type(&self) -> HapType {
         self.hap_type
     }
@@ -625,6 +799,14 @@ impl HapService for {{pascal_case service.Name}}Service {
         self.primary = primary;
     }
 
+    fn get_linked_services(&self) -> Vec<u64> {
+        self.linked_services.clone()
+    }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) {
+        self.linked_services = linked_services;
+    }
+
     fn get_characteristic(&self, hap_type: HapType) -> Option<&dyn HapCharacteristic> {
         for characteristic in self.get_characteristics() {
             if characteristic.get_type() == hap_type {
@@ -674,13 +856,15 @@ impl HapService for {{pascal_case service.Name}}Service {
 
 impl Serialize for {{pascal_case service.Name}}Service {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct(\"HapService\", 5)?;
+        let mut state = serializer.serialize_struct(\"HapService\", 6)?;
         state.serialize_field(\"iid\", &self.get_id())?;
         state.serialize_field(\"type\", &self.get_type())?;
         state.serialize_field(\"hidden\", &self.get_hidden())?;
         state.serialize_field(\"primary\", &self.get_primary())?;
         state.serialize_field(\"characteristics\", &self.get_characteristics())?;
-        // linked services left out for now
+        if !self.get_linked_services().is_empty() {
+            state.serialize_field(\"linked\", &self.get_linked_services())?;
+        }
         state.end()
     }
 }
@@ -688,6 +872,22 @@ impl Serialize for {{pascal_case service.Name}}Service {
 
 static SERVICE_MOD: &'static str = "// THIS FILE IS AUTO-GENERATED
 {{#each services as |s|}}\npub mod {{s}};{{/each}}
+
+use crate::HapType;
+
+/// Returns the types of the required and the optional Characteristics of a Service type. Returns `None` if the
+/// `HapType` isn't a Service type.
+pub(crate) fn service_characteristic_types(hap_type: HapType) -> Option<(&'static [HapType], &'static [HapType])> {
+    match hap_type {
+{{#each definitions as |s|}}\
+\t\tHapType::{{pascal_case s.name}} => Some((
+\t\t\t&[{{#each s.required as |r|}}HapType::{{pascal_case r}}, {{/each}}],
+\t\t\t&[{{#each s.optional as |o|}}HapType::{{pascal_case o}}, {{/each}}],
+\t\t)),
+{{/each}}\
+\t\t_ => None,
+    }
+}
 ";
 
 static ACCESSORY: &'static str = "// THIS FILE IS AUTO-GENERATED\n
@@ -695,7 +895,12 @@ use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
 \taccessory::{AccessoryInformation, HapAccessory},
-\tservice::{HapService, accessory_information::AccessoryInformationService, {{snake_case service.Name}}::{{pascal_case service.Name}}Service},
+\tcharacteristic::CharacteristicOverrides,
+\tservice::{
+\t\tHapService,
+\t\taccessory_information::AccessoryInformationService,
+\t\t{{snake_case service.Name}}::{ {{~pascal_case service.Name}}Service, {{pascal_case service.Name}}ServiceBuilder},
+\t},
 \tHapType,
 \tResult,
 };
@@ -726,6 +931,53 @@ impl {{pascal_case service.Name}}Accessory {
             {{snake_case service.Name}},
         })
     }
+
+    /// Returns a builder for a {{service.Name}} Accessory with optional Characteristics of its {{service.Name}} Service
+    /// enabled.
+    pub fn builder(information: AccessoryInformation) -> {{pascal_case service.Name}}AccessoryBuilder {
+        {{pascal_case service.Name}}AccessoryBuilder {
+            information,
+            {{snake_case service.Name}}: {{pascal_case service.Name}}Service::builder(),
+        }
+    }
+}
+
+/// Builder for a {{service.Name}} Accessory, enabling optional Characteristics and overriding Characteristic metadata
+/// of its {{service.Name}} Service.
+#[derive(Debug)]
+pub struct {{pascal_case service.Name}}AccessoryBuilder {
+    information: AccessoryInformation,
+    {{snake_case service.Name}}: {{pascal_case service.Name}}ServiceBuilder,
+}
+
+impl {{pascal_case service.Name}}AccessoryBuilder {
+{{#each optional_characteristics as |r|}}\
+\t/// Enables the {{r.Name}} Characteristic.
+\tpub fn with_{{snake_case r.Name}}(mut self) -> Self {
+\t\tself.{{snake_case ../service.Name}} = self.{{snake_case ../service.Name}}.with_{{snake_case r.Name}}();
+\t\tself
+\t}
+
+{{/each}}\
+\t/// Overrides metadata of a required or enabled optional Characteristic of the {{service.Name}} Service.
+    pub fn with_overrides(mut self, hap_type: HapType, overrides: CharacteristicOverrides) -> Self {
+        self.{{snake_case service.Name}} = self.{{snake_case service.Name}}.with_overrides(hap_type, overrides);
+        self
+    }
+
+    /// Builds the {{service.Name}} Accessory.
+    pub fn build(self, id: u64) -> Result<{{pascal_case service.Name}}Accessory> {
+        let accessory_information = self.information.to_service(1, id)?;
+        let {{snake_case service.Name}}_id = accessory_information.get_characteristics().len() as u64;
+        let mut {{snake_case service.Name}} = self.{{snake_case service.Name}}.build(1 + {{snake_case service.Name}}_id + 1, id)?;
+        {{snake_case service.Name}}.set_primary(true);
+
+        Ok({{pascal_case service.Name}}Accessory {
+            id,
+            accessory_information,
+            {{snake_case service.Name}},
+        })
+    }
 }
 
 impl HapAccessory for {{pascal_case service.Name}}Accessory {
@@ -867,6 +1119,7 @@ fn main() {
     handlebars.register_helper("float", Box::new(float_helper));
     handlebars.register_helper("snake_case", Box::new(snake_case_helper));
     handlebars.register_helper("pascal_case", Box::new(pascal_case_helper));
+    handlebars.register_helper("lookup_name", Box::new(lookup_name_helper));
     handlebars.register_template_string("categories", CATEGORIES).unwrap();
     handlebars.register_template_string("hap_type", HAP_TYPE).unwrap();
     handlebars
@@ -914,7 +1167,7 @@ fn main() {
     let characteristic_mod = handlebars
         .render(
             "characteristic_mod",
-            &json!({ "characteristics": characteristsic_names }),
+            &json!({ "characteristics": characteristsic_names, "definitions": &metadata.characteristics }),
         )
         .unwrap();
     let mut characteristic_mod_file = File::create(&format!("{}mod.rs", characteristic_base_path)).unwrap();
@@ -933,6 +1186,7 @@ fn main() {
     fs::create_dir_all(&service_base_path).unwrap();
     fs::create_dir_all(&accessory_base_path).unwrap();
     let mut service_names = vec![];
+    let mut service_definitions = vec![];
     let mut accessory_names = vec![];
     for s in &metadata.services {
         let mut required_characteristics = Vec::new();
@@ -946,6 +1200,12 @@ fn main() {
             optional_characteristics.push(metadata_ex.characteristics[c]);
         }
 
+        service_definitions.push(json!({
+            "name": s.name,
+            "required": required_characteristics.iter().map(|c| &c.name).collect::<Vec<_>>(),
+            "optional": optional_characteristics.iter().map(|c| &c.name).collect::<Vec<_>>(),
+        }));
+
         let service = handlebars
             .render(
                 "service",
@@ -984,7 +1244,11 @@ fn main() {
             let accessory = handlebars
                 .render(
                     "accessory",
-                    &json!({"service": s, "characteristics": &metadata.characteristics}),
+                    &json!({
+                        "service": s,
+                        "characteristics": &metadata.characteristics,
+                        "optional_characteristics": &optional_characteristics,
+                    }),
                 )
                 .unwrap();
             let mut accessory_path = String::from(accessory_base_path);
@@ -996,7 +1260,10 @@ fn main() {
         }
     }
     let service_mod = handlebars
-        .render("service_mod", &json!({ "services": service_names }))
+        .render(
+            "service_mod",
+            &json!({ "services": service_names, "definitions": service_definitions }),
+        )
         .unwrap();
     let mut service_mod_file = File::create(&format!("{}mod.rs", service_base_path)).unwrap();
     service_mod_file.write_all(service_mod.as_bytes()).unwrap();
@@ -58,7 +58,7 @@ async fn main() {
         Err(_) => {
             let config = Config {
                 socket_addr: SocketAddr::new(current_ipv4().unwrap(), 32000),
-                pin: Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap(),
+                pin: Some(Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap()),
                 name: "Acme Lightbulb".into(),
                 device_id: MacAddress::new([10, 20, 30, 40, 50, 60]),
                 category: AccessoryCategory::Lightbulb,
@@ -0,0 +1,477 @@
+//! Derive macros for the `HapAccessory` and `HapService` traits of the `hap` crate.
+//!
+//! Both macros are re-exported by `hap` alongside the traits they implement and are meant to be used from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input,
+    punctuated::Punctuated,
+    token::Comma,
+    Data,
+    DeriveInput,
+    Error,
+    Field,
+    Fields,
+    Ident,
+    Meta,
+    NestedMeta,
+    PathArguments,
+    Result,
+    Type,
+};
+
+/// Derives `HapAccessory` and `Serialize` for a struct with an `id: u64` field and Service fields marked with
+/// `#[service]`. Service fields of type `Option<_>` are only included if they are set, and fields of type `Vec<_>`
+/// include all of their Services. Services are linked by naming the fields of the linked Services in
+/// `#[service(linked(...))]`. The struct additionally gets an `assign_ids` method numbering its Services and
+/// Characteristics and resolving the links to the assigned IDs.
+///
+/// ```ignore
+/// use hap::{
+///     accessory::HapAccessory,
+///     service::{accessory_information::AccessoryInformationService, faucet::FaucetService, valve::ValveService},
+/// };
+///
+/// #[derive(HapAccessory)]
+/// pub struct CustomAccessory {
+///     id: u64,
+///     #[service]
+///     pub accessory_information: AccessoryInformationService,
+///     #[service(linked(valves))]
+///     pub faucet: FaucetService,
+///     #[service]
+///     pub valves: Vec<ValveService>,
+/// }
+/// ```
+#[proc_macro_derive(HapAccessory, attributes(service))]
+pub fn derive_hap_accessory(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_hap_accessory(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Derives `HapService` and `Serialize` for a struct with `id: u64`, `hap_type: HapType`, `hidden: bool`,
+/// `primary: bool` and `linked_services: Vec<u64>` fields and Characteristic fields marked with `#[characteristic]`.
+/// Characteristic fields of type `Option<_>` are only included if they are set, and fields of type `Vec<_>` include all
+/// of their Characteristics.
+///
+/// ```ignore
+/// use hap::{
+///     characteristic::{brightness::BrightnessCharacteristic, on::OnCharacteristic},
+///     service::HapService,
+///     HapType,
+/// };
+///
+/// #[derive(HapService)]
+/// pub struct CustomService {
+///     id: u64,
+///     hap_type: HapType,
+///     hidden: bool,
+///     primary: bool,
+///     linked_services: Vec<u64>,
+///     #[characteristic]
+///     pub on: OnCharacteristic,
+///     #[characteristic]
+///     pub brightness: Option<BrightnessCharacteristic>,
+/// }
+/// ```
+#[proc_macro_derive(HapService, attributes(characteristic))]
+pub fn derive_hap_service(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_hap_service(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand_hap_accessory(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = named_fields(input, "HapAccessory")?;
+    require_fields(input, fields, &["id"])?;
+    let services = marked_fields(fields, "service");
+    let link_services = link_fields(fields, &services)?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let get_services = collect_fields(
+        &services,
+        quote! { &dyn ::hap::service::HapService },
+        quote! { & },
+        quote! { services },
+    );
+    let get_mut_services = collect_fields(
+        &services,
+        quote! { &mut dyn ::hap::service::HapService },
+        quote! { &mut },
+        quote! { services },
+    );
+
+    Ok(quote! {
+        impl #impl_generics ::hap::accessory::HapAccessory for #name #ty_generics #where_clause {
+            fn get_id(&self) -> u64 { self.id }
+
+            fn set_id(&mut self, id: u64) { self.id = id; }
+
+            fn get_service(&self, hap_type: ::hap::HapType) -> Option<&dyn ::hap::service::HapService> {
+                for service in self.get_services() {
+                    if service.get_type() == hap_type {
+                        return Some(service);
+                    }
+                }
+                None
+            }
+
+            fn get_mut_service(
+                &mut self,
+                hap_type: ::hap::HapType,
+            ) -> Option<&mut dyn ::hap::service::HapService> {
+                for service in self.get_mut_services() {
+                    if service.get_type() == hap_type {
+                        return Some(service);
+                    }
+                }
+                None
+            }
+
+            fn get_services(&self) -> Vec<&dyn ::hap::service::HapService> { #get_services }
+
+            fn get_mut_services(&mut self) -> Vec<&mut dyn ::hap::service::HapService> { #get_mut_services }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Sets the ID of the Accessory and assigns consecutive instance IDs to its Services and their
+            /// Characteristics, starting at 1 and following the order of `get_services` and `get_characteristics`.
+            /// Services declared with `#[service(linked(...))]` are linked to the assigned IDs of the named Services.
+            pub fn assign_ids(&mut self, aid: u64) {
+                use ::hap::{accessory::HapAccessory, service::HapService};
+
+                self.set_id(aid);
+                let mut next_id = 1;
+                for service in self.get_mut_services() {
+                    service.set_id(next_id);
+                    let characteristic_count = service.get_characteristics().len() as u64;
+                    for (characteristic, id) in service.get_mut_characteristics().into_iter().zip(next_id + 1..) {
+                        characteristic.set_id(id);
+                        characteristic.set_accessory_id(aid);
+                    }
+                    next_id += 1 + characteristic_count;
+                }
+
+                #link_services
+            }
+        }
+
+        impl #impl_generics ::hap::serde::Serialize for #name #ty_generics #where_clause {
+            fn serialize<S: ::hap::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+                use ::hap::{accessory::HapAccessory, serde::ser::SerializeStruct};
+
+                let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+                state.serialize_field("aid", &self.get_id())?;
+                state.serialize_field("services", &self.get_services())?;
+                state.end()
+            }
+        }
+    })
+}
+
+fn expand_hap_service(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = named_fields(input, "HapService")?;
+    require_fields(input, fields, &[
+        "id",
+        "hap_type",
+        "hidden",
+        "primary",
+        "linked_services",
+    ])?;
+    let characteristics = marked_fields(fields, "characteristic");
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let get_characteristics = collect_fields(
+        &characteristics,
+        quote! { &dyn ::hap::characteristic::HapCharacteristic },
+        quote! { & },
+        quote! { characteristics },
+    );
+    let get_mut_characteristics = collect_fields(
+        &characteristics,
+        quote! { &mut dyn ::hap::characteristic::HapCharacteristic },
+        quote! { &mut },
+        quote! { characteristics },
+    );
+
+    Ok(quote! {
+        impl #impl_generics ::hap::service::HapService for #name #ty_generics #where_clause {
+            fn get_id(&self) -> u64 { self.id }
+
+            fn set_id(&mut self, id: u64) { self.id = id; }
+
+            fn get_type(&self) -> ::hap::HapType { self.hap_type }
+
+            fn get_hidden(&self) -> bool { self.hidden }
+
+            fn set_hidden(&mut self, hidden: bool) { self.hidden = hidden; }
+
+            fn get_primary(&self) -> bool { self.primary }
+
+            fn set_primary(&mut self, primary: bool) { self.primary = primary; }
+
+            fn get_linked_services(&self) -> Vec<u64> { self.linked_services.clone() }
+
+            fn set_linked_services(&mut self, linked_services: Vec<u64>) { self.linked_services = linked_services; }
+
+            fn get_characteristic(
+                &self,
+                hap_type: ::hap::HapType,
+            ) -> Option<&dyn ::hap::characteristic::HapCharacteristic> {
+                for characteristic in self.get_characteristics() {
+                    if characteristic.get_type() == hap_type {
+                        return Some(characteristic);
+                    }
+                }
+                None
+            }
+
+            fn get_mut_characteristic(
+                &mut self,
+                hap_type: ::hap::HapType,
+            ) -> Option<&mut dyn ::hap::characteristic::HapCharacteristic> {
+                for characteristic in self.get_mut_characteristics() {
+                    if characteristic.get_type() == hap_type {
+                        return Some(characteristic);
+                    }
+                }
+                None
+            }
+
+            fn get_characteristics(&self) -> Vec<&dyn ::hap::characteristic::HapCharacteristic> {
+                #get_characteristics
+            }
+
+            fn get_mut_characteristics(&mut self) -> Vec<&mut dyn ::hap::characteristic::HapCharacteristic> {
+                #get_mut_characteristics
+            }
+        }
+
+        impl #impl_generics ::hap::serde::Serialize for #name #ty_generics #where_clause {
+            fn serialize<S: ::hap::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+                use ::hap::{serde::ser::SerializeStruct, service::HapService};
+
+                let mut state = serializer.serialize_struct("HapService", 6)?;
+                state.serialize_field("iid", &self.get_id())?;
+                state.serialize_field("type", &self.get_type())?;
+                state.serialize_field("hidden", &self.get_hidden())?;
+                state.serialize_field("primary", &self.get_primary())?;
+                state.serialize_field("characteristics", &self.get_characteristics())?;
+                if !self.get_linked_services().is_empty() {
+                    state.serialize_field("linked", &self.get_linked_services())?;
+                }
+                state.end()
+            }
+        }
+    })
+}
+
+/// A struct field marked with one of the helper attributes.
+struct MarkedField<'a> {
+    ident: &'a Ident,
+    kind: FieldKind,
+}
+
+/// How a marked field holds its Services or Characteristics.
+enum FieldKind {
+    /// The field holds exactly one.
+    Single,
+    /// The field is an `Option<_>` holding zero or one.
+    Optional,
+    /// The field is a `Vec<_>` holding any number.
+    Multiple,
+}
+
+fn named_fields<'a>(input: &'a DeriveInput, derive: &str) -> Result<&'a Punctuated<Field, Comma>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(Error::new(
+                Span::call_site(),
+                format!("#[derive({})] requires a struct with named fields", derive),
+            )),
+        },
+        _ => Err(Error::new(
+            Span::call_site(),
+            format!("#[derive({})] can only be used on structs", derive),
+        )),
+    }
+}
+
+fn require_fields(input: &DeriveInput, fields: &Punctuated<Field, Comma>, names: &[&str]) -> Result<()> {
+    for name in names {
+        if !fields.iter().any(|f| f.ident.as_ref().map_or(false, |i| i == name)) {
+            return Err(Error::new_spanned(
+                &input.ident,
+                format!("`{}` is missing the required field `{}`", input.ident, name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn marked_fields<'a>(fields: &'a Punctuated<Field, Comma>, attribute: &str) -> Vec<MarkedField<'a>> {
+    fields
+        .iter()
+        .filter(|f| f.attrs.iter().any(|a| a.path.is_ident(attribute)))
+        .filter_map(|f| {
+            f.ident.as_ref().map(|ident| MarkedField {
+                ident,
+                kind: if is_wrapped_in(&f.ty, "Option") {
+                    FieldKind::Optional
+                } else if is_wrapped_in(&f.ty, "Vec") {
+                    FieldKind::Multiple
+                } else {
+                    FieldKind::Single
+                },
+            })
+        })
+        .collect()
+}
+
+/// Builds the statements setting the linked Services of the Service fields marked with `#[service(linked(...))]` to
+/// the current IDs of the named Service fields.
+fn link_fields(fields: &Punctuated<Field, Comma>, services: &[MarkedField]) -> Result<TokenStream2> {
+    let mut statements = Vec::new();
+    for field in fields {
+        let ident = match field.ident.as_ref() {
+            Some(ident) => ident,
+            None => continue,
+        };
+        let kind = match services.iter().find(|s| s.ident == ident) {
+            Some(service) => &service.kind,
+            None => continue,
+        };
+
+        let mut linked = Vec::new();
+        for attr in field.attrs.iter().filter(|a| a.path.is_ident("service")) {
+            let list = match attr.parse_meta()? {
+                Meta::Path(_) => continue,
+                Meta::List(list) => list,
+                meta =>
+                    return Err(Error::new_spanned(
+                        meta,
+                        "expected `#[service]` or `#[service(linked(...))]`",
+                    )),
+            };
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::List(ref l)) if l.path.is_ident("linked") =>
+                        for linked_field in &l.nested {
+                            let linked_service = match linked_field {
+                                NestedMeta::Meta(Meta::Path(path)) => services.iter().find(|s| path.is_ident(s.ident)),
+                                _ => None,
+                            };
+                            match linked_service {
+                                Some(linked_service) if linked_service.ident != ident => linked.push(linked_service),
+                                _ =>
+                                    return Err(Error::new_spanned(
+                                        linked_field,
+                                        "expected the name of another field marked with `#[service]`",
+                                    )),
+                            }
+                        },
+                    nested => return Err(Error::new_spanned(nested, "expected `linked(...)`")),
+                }
+            }
+        }
+        if linked.is_empty() {
+            continue;
+        }
+
+        let pushes = linked.iter().map(|l| {
+            let linked_ident = l.ident;
+            match l.kind {
+                FieldKind::Single => quote! {
+                    linked_services.push(self.#linked_ident.get_id());
+                },
+                FieldKind::Optional => quote! {
+                    if let Some(s) = &self.#linked_ident {
+                        linked_services.push(s.get_id());
+                    }
+                },
+                FieldKind::Multiple => quote! {
+                    for s in &self.#linked_ident {
+                        linked_services.push(s.get_id());
+                    }
+                },
+            }
+        });
+        let set = match kind {
+            FieldKind::Single => quote! {
+                self.#ident.set_linked_services(linked_services);
+            },
+            FieldKind::Optional => quote! {
+                if let Some(s) = &mut self.#ident {
+                    s.set_linked_services(linked_services);
+                }
+            },
+            FieldKind::Multiple => quote! {
+                for s in &mut self.#ident {
+                    s.set_linked_services(linked_services.clone());
+                }
+            },
+        };
+        statements.push(quote! {
+            {
+                let mut linked_services = Vec::new();
+                #(#pushes)*
+                #set
+            }
+        });
+    }
+
+    Ok(quote! { #(#statements)* })
+}
+
+/// Builds the body of a getter collecting references to the marked fields into a `Vec` in the order they're declared
+/// in, skipping unset optional ones.
+fn collect_fields(
+    fields: &[MarkedField],
+    item: TokenStream2,
+    reference: TokenStream2,
+    vec: TokenStream2,
+) -> TokenStream2 {
+    let pushes = fields.iter().map(|f| {
+        let ident = f.ident;
+        match f.kind {
+            FieldKind::Single => quote! {
+                #vec.push(#reference self.#ident);
+            },
+            FieldKind::Optional => quote! {
+                if let Some(f) = #reference self.#ident {
+                    #vec.push(f);
+                }
+            },
+            FieldKind::Multiple => quote! {
+                for f in #reference self.#ident {
+                    #vec.push(f);
+                }
+            },
+        }
+    });
+
+    quote! {
+        #[allow(unused_mut)]
+        let mut #vec: Vec<#item> = Vec::new();
+        #(#pushes)*
+        #vec
+    }
+}
+
+/// Checks if a type is the given generic wrapper, e.g. `Option<_>`.
+fn is_wrapped_in(ty: &Type, wrapper: &str) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last().map_or(false, |s| {
+            s.ident == wrapper && matches!(s.arguments, PathArguments::AngleBracketed(_))
+        }),
+        _ => false,
+    }
+}
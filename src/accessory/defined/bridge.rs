@@ -1,19 +1,17 @@
-use serde::ser::{Serialize, SerializeStruct, Serializer};
-
 use crate::{
     accessory::{AccessoryInformation, HapAccessory},
-    service::{accessory_information::AccessoryInformationService, HapService},
-    HapType,
+    service::accessory_information::AccessoryInformationService,
     Result,
 };
 
 /// Bridge Accessory.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, HapAccessory)]
 pub struct BridgeAccessory {
     /// ID of the Bridge Accessory.
     id: u64,
 
     /// Accessory Information Service.
+    #[service]
     pub accessory_information: AccessoryInformationService,
 }
 
@@ -28,40 +26,3 @@ impl BridgeAccessory {
         })
     }
 }
-
-impl HapAccessory for BridgeAccessory {
-    fn get_id(&self) -> u64 { self.id }
-
-    fn set_id(&mut self, id: u64) { self.id = id; }
-
-    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
-        for service in self.get_services() {
-            if service.get_type() == hap_type {
-                return Some(service);
-            }
-        }
-        None
-    }
-
-    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
-        for service in self.get_mut_services() {
-            if service.get_type() == hap_type {
-                return Some(service);
-            }
-        }
-        None
-    }
-
-    fn get_services(&self) -> Vec<&dyn HapService> { vec![&self.accessory_information] }
-
-    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> { vec![&mut self.accessory_information] }
-}
-
-impl Serialize for BridgeAccessory {
-    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
-        state.serialize_field("aid", &self.get_id())?;
-        state.serialize_field("services", &self.get_services())?;
-        state.end()
-    }
-}
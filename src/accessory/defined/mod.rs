@@ -1,5 +1,6 @@
 pub mod bridge;
 pub mod ip_camera;
 pub mod lock;
+pub mod programmable_switch;
 pub mod television;
 pub mod video_doorbell;
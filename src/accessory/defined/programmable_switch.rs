@@ -0,0 +1,80 @@
+use futures::executor;
+
+use crate::{
+    accessory::{AccessoryInformation, HapAccessory},
+    service::{
+        accessory_information::AccessoryInformationService,
+        service_label::ServiceLabelService,
+        stateless_programmable_switch::StatelessProgrammableSwitchService,
+        HapService,
+    },
+    Error,
+    Result,
+};
+
+/// Naming scheme of the buttons of a Programmable Switch Accessory, as shown by controllers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ServiceLabelNamespace {
+    /// The buttons are labeled with dots.
+    Dots = 0,
+    /// The buttons are labeled with arabic numerals.
+    ArabicNumerals = 1,
+}
+
+/// Programmable Switch Accessory with multiple buttons, e.g. a remote. Every button is a Stateless Programmable Switch
+/// Service carrying a Service Label Index, which controllers display using the namespace of the Service Label Service.
+#[derive(Debug, Default, HapAccessory)]
+pub struct ProgrammableSwitchAccessory {
+    /// ID of the Programmable Switch Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    #[service]
+    pub accessory_information: AccessoryInformationService,
+    /// Service Label Service.
+    #[service]
+    pub service_label: ServiceLabelService,
+    /// Stateless Programmable Switch Services, one per button, with Service Label Indexes starting at 1.
+    #[service]
+    pub switches: Vec<StatelessProgrammableSwitchService>,
+}
+
+impl ProgrammableSwitchAccessory {
+    /// Creates a new Programmable Switch Accessory with the given number of buttons. There has to be at least one
+    /// button.
+    pub fn new(
+        id: u64,
+        information: AccessoryInformation,
+        button_count: u8,
+        namespace: ServiceLabelNamespace,
+    ) -> Result<Self> {
+        if button_count == 0 {
+            return Err(Error::NoButtons);
+        }
+
+        let mut service_label = ServiceLabelService::new(0, id);
+        executor::block_on(service_label.service_label_namespace.set_value(namespace as u8))?;
+
+        let mut switches = Vec::new();
+        for index in 1..=button_count {
+            let mut switch = StatelessProgrammableSwitchService::builder()
+                .with_service_label_index()
+                .build(0, id)?;
+            if let Some(service_label_index) = switch.service_label_index.as_mut() {
+                executor::block_on(service_label_index.set_value(index))?;
+            }
+            switches.push(switch);
+        }
+        switches[0].set_primary(true);
+
+        let mut programmable_switch = Self {
+            id,
+            accessory_information: information.to_service(1, id)?,
+            service_label,
+            switches,
+        };
+        programmable_switch.assign_ids(id);
+
+        Ok(programmable_switch)
+    }
+}
@@ -0,0 +1,321 @@
+use serde::{
+    ser::{Serialize, SerializeStruct, Serializer},
+    Deserialize,
+};
+
+use crate::{
+    accessory::HapAccessory,
+    characteristic::HapCharacteristic,
+    service::{DynamicService, HapService, ServiceDescription},
+    Error,
+    HapType,
+    Result,
+};
+
+/// Declarative description of an Accessory, e.g. read from a configuration file. It can be deserialized from JSON, or
+/// from any other format supported by serde, e.g. YAML with `serde_yaml`.
+///
+/// The description has to include an Accessory Information Service.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::DynamicAccessory;
+///
+/// let accessory = DynamicAccessory::from_json(
+///     r#"{
+///         "id": 2,
+///         "services": [
+///             {
+///                 "type": "Accessory Information",
+///                 "characteristics": [
+///                     { "type": "Name", "value": "Desk Lamp" },
+///                     { "type": "Manufacturer", "value": "Acme" },
+///                     { "type": "Model", "value": "L1" },
+///                     { "type": "Serial Number", "value": "1A2B3C" },
+///                     { "type": "Firmware Revision", "value": "1.0.0" }
+///                 ]
+///             },
+///             {
+///                 "type": "Lightbulb",
+///                 "primary": true,
+///                 "characteristics": [{ "type": "Brightness", "persistent": true }]
+///             }
+///         ]
+///     }"#,
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessoryDescription {
+    /// ID of the Accessory.
+    pub id: u64,
+    /// The Services of the Accessory.
+    pub services: Vec<ServiceDescription>,
+}
+
+/// An Accessory constructed at runtime, e.g. from an `AccessoryDescription`, instead of being defined at compile time.
+#[derive(Debug, Default)]
+pub struct DynamicAccessory {
+    /// ID of the Accessory.
+    id: u64,
+
+    /// The Services of the Accessory.
+    services: Vec<DynamicService>,
+    /// Positions of the Services linked to each Service, resolved to their IDs by `assign_ids`.
+    links: Vec<Vec<usize>>,
+}
+
+impl DynamicAccessory {
+    /// Creates a new Accessory without any Services.
+    pub fn new(id: u64) -> Self {
+        DynamicAccessory {
+            id,
+            services: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+
+    /// Creates a new Accessory from a description. The Services and their Characteristics are given consecutive IDs in
+    /// the order they're described in, and linked Services are resolved to those IDs.
+    pub fn from_description(description: &AccessoryDescription) -> Result<Self> {
+        let mut accessory = Self::new(description.id);
+        for service_description in &description.services {
+            let service = DynamicService::from_description(0, accessory.id, service_description)?;
+            accessory.services.push(service);
+            accessory.links.push(Vec::new());
+        }
+        for (position, service_description) in description.services.iter().enumerate() {
+            for &linked_position in &service_description.linked {
+                accessory.link_services(position, linked_position)?;
+            }
+        }
+        accessory.assign_ids(description.id);
+
+        Ok(accessory)
+    }
+
+    /// Creates a new Accessory from a JSON description.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let description: AccessoryDescription = serde_json::from_str(json)?;
+        Self::from_description(&description)
+    }
+
+    /// Adds a Service of the given type with all of its required Characteristics and returns a mutable reference to
+    /// it.
+    pub fn add_service(&mut self, hap_type: HapType) -> Result<&mut DynamicService> {
+        let service = DynamicService::new(self.next_id(), self.id, hap_type)?;
+        self.services.push(service);
+        self.links.push(Vec::new());
+
+        Ok(self.services.last_mut().unwrap())
+    }
+
+    /// Enables an optional Characteristic of the Service at the given position and returns a mutable reference to it.
+    /// The IDs of the Services following it are reassigned with `assign_ids` to make room for the Characteristic.
+    pub fn add_characteristic(&mut self, service: usize, hap_type: HapType) -> Result<&mut dyn HapCharacteristic> {
+        self.services
+            .get_mut(service)
+            .ok_or(Error::InvalidServicePosition(service))?
+            .add_characteristic(hap_type)?;
+        self.assign_ids(self.id);
+
+        Ok(self.services[service]
+            .get_mut_characteristic(hap_type)
+            .expect("the characteristic was just added"))
+    }
+
+    /// Links the Service at the position `linked` to the Service at the position `service`. The link is kept by
+    /// position, so it stays correct when the IDs are reassigned.
+    pub fn link_services(&mut self, service: usize, linked: usize) -> Result<()> {
+        if linked >= self.services.len() {
+            return Err(Error::InvalidLinkedService(linked as u64));
+        }
+        let links = self
+            .links
+            .get_mut(service)
+            .ok_or(Error::InvalidServicePosition(service))?;
+        if !links.contains(&linked) {
+            links.push(linked);
+        }
+        self.resolve_links();
+
+        Ok(())
+    }
+
+    /// Sets the ID of the Accessory and assigns consecutive instance IDs to its Services and their Characteristics,
+    /// starting at 1 and following the order of the Services and their Characteristics. Linked Services are resolved
+    /// to the assigned IDs.
+    pub fn assign_ids(&mut self, aid: u64) {
+        self.set_id(aid);
+        let mut next_id = 1;
+        for service in &mut self.services {
+            service.set_id(next_id);
+            let characteristic_count = service.get_characteristics().len() as u64;
+            for (characteristic, id) in service.get_mut_characteristics().into_iter().zip(next_id + 1..) {
+                characteristic.set_id(id);
+                characteristic.set_accessory_id(aid);
+            }
+            next_id += 1 + characteristic_count;
+        }
+
+        self.resolve_links();
+    }
+
+    /// Sets the linked Services of every Service to the current IDs of the Services at the linked positions.
+    fn resolve_links(&mut self) {
+        let service_ids = self.services.iter().map(|s| s.get_id()).collect::<Vec<_>>();
+        for (service, links) in self.services.iter_mut().zip(&self.links) {
+            service.set_linked_services(links.iter().map(|&position| service_ids[position]).collect());
+        }
+    }
+
+    /// Returns the next free instance ID following the ones of the existing Services and Characteristics.
+    fn next_id(&self) -> u64 {
+        self.services
+            .iter()
+            .map(|s| s.get_id() + s.get_characteristics().len() as u64 + 1)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+impl HapAccessory for DynamicAccessory {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_service(&self, hap_type: HapType) -> Option<&dyn HapService> {
+        for service in self.get_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_mut_service(&mut self, hap_type: HapType) -> Option<&mut dyn HapService> {
+        for service in self.get_mut_services() {
+            if service.get_type() == hap_type {
+                return Some(service);
+            }
+        }
+        None
+    }
+
+    fn get_services(&self) -> Vec<&dyn HapService> { self.services.iter().map(|s| s as &dyn HapService).collect() }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapService> {
+        self.services.iter_mut().map(|s| s as &mut dyn HapService).collect()
+    }
+}
+
+impl Serialize for DynamicAccessory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapAccessory", 2)?;
+        state.serialize_field("aid", &self.get_id())?;
+        state.serialize_field("services", &self.get_services())?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_from_json() {
+        let mut accessory = DynamicAccessory::from_json(
+            r#"{
+                "id": 2,
+                "services": [
+                    {
+                        "type": "Accessory Information",
+                        "characteristics": [{ "type": "Name", "value": "Desk Lamp" }]
+                    },
+                    {
+                        "type": "00000043-0000-1000-8000-0026BB765291",
+                        "primary": true,
+                        "characteristics": [{ "type": "Brightness", "value": 50, "max_value": 80 }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let information = accessory.get_service(HapType::AccessoryInformation).unwrap();
+        assert_eq!(information.get_id(), 1);
+        let information_len = information.get_characteristics().len() as u64;
+
+        let lightbulb = accessory.get_mut_service(HapType::Lightbulb).unwrap();
+        assert_eq!(lightbulb.get_id(), 1 + information_len + 1);
+        assert!(lightbulb.get_primary());
+        assert_eq!(lightbulb.get_characteristics().len(), 2);
+
+        let brightness = lightbulb.get_mut_characteristic(HapType::Brightness).unwrap();
+        assert_eq!(brightness.get_value().await.unwrap(), serde_json::json!(50));
+        assert_eq!(brightness.get_max_value(), Some(serde_json::json!(80)));
+
+        // the On Characteristic is required, not optional
+        assert!(DynamicService::new(10, 2, HapType::Lightbulb)
+            .unwrap()
+            .add_characteristic(HapType::On)
+            .is_err());
+        assert!(DynamicAccessory::from_json(r#"{ "id": 2, "services": [{ "type": "Toaster" }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_linked_services() {
+        let accessory = DynamicAccessory::from_json(
+            r#"{
+                "id": 2,
+                "services": [
+                    { "type": "Accessory Information" },
+                    { "type": "Faucet", "primary": true, "linked": [2] },
+                    { "type": "Valve" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let valve_id = accessory.get_service(HapType::Valve).unwrap().get_id();
+        let faucet = accessory.get_service(HapType::Faucet).unwrap();
+        assert_eq!(faucet.get_linked_services(), vec![valve_id]);
+
+        let json = serde_json::to_value(&accessory).unwrap();
+        assert_eq!(json["services"][1]["linked"], serde_json::json!([valve_id]));
+        assert!(json["services"][2].get("linked").is_none());
+
+        assert!(DynamicAccessory::from_json(
+            r#"{ "id": 2, "services": [{ "type": "Accessory Information" }, { "type": "Faucet", "linked": [5] }] }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_add_characteristic() {
+        let mut accessory = DynamicAccessory::new(2);
+        accessory.add_service(HapType::AccessoryInformation).unwrap();
+        accessory.add_service(HapType::Lightbulb).unwrap();
+        accessory.add_service(HapType::Switch).unwrap();
+        accessory.link_services(1, 2).unwrap();
+
+        let brightness_id = accessory.add_characteristic(1, HapType::Brightness).unwrap().get_id();
+
+        // the Switch Service and its Characteristic are moved behind the new Characteristic
+        let switch_id = accessory.get_service(HapType::Switch).unwrap().get_id();
+        assert_eq!(switch_id, brightness_id + 1);
+        let lightbulb = accessory.get_service(HapType::Lightbulb).unwrap();
+        assert_eq!(lightbulb.get_linked_services(), vec![switch_id]);
+
+        let mut ids = Vec::new();
+        for service in accessory.get_services() {
+            ids.push(service.get_id());
+            ids.extend(service.get_characteristics().iter().map(|c| c.get_id()));
+        }
+        assert_eq!(ids, (1..=ids.len() as u64).collect::<Vec<_>>());
+
+        assert!(accessory.add_characteristic(3, HapType::Brightness).is_err());
+        assert!(accessory.link_services(1, 3).is_err());
+    }
+}
@@ -1,5 +1,10 @@
+use std::sync::Arc;
+
 use erased_serde::serialize_trait_object;
-use futures::executor;
+use futures::{
+    executor,
+    lock::{Mutex, MutexGuard},
+};
 
 use crate::{
     characteristic::{
@@ -15,9 +20,17 @@ use crate::{
 
 mod category;
 mod defined;
+mod dynamic;
 mod generated;
 
-pub use crate::accessory::{category::AccessoryCategory, defined::*, generated::*};
+pub use hap_derive::HapAccessory;
+
+pub use crate::accessory::{
+    category::AccessoryCategory,
+    defined::*,
+    dynamic::{AccessoryDescription, DynamicAccessory},
+    generated::*,
+};
 
 /// `HapAccessory` is implemented by the inner type of every `Accessory`.
 pub trait HapAccessory: HapAccessorySetup + erased_serde::Serialize + Send + Sync {
@@ -33,6 +46,44 @@ pub trait HapAccessory: HapAccessorySetup + erased_serde::Serialize + Send + Syn
     fn get_services(&self) -> Vec<&dyn HapService>;
     /// Returns mutable references to the Services of the Accessory.
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapService>;
+    /// Returns a reference to the Service with the given instance ID if it's present on the Accessory.
+    fn get_service_by_id(&self, id: u64) -> Option<&dyn HapService> {
+        self.get_services().into_iter().find(|s| s.get_id() == id)
+    }
+    /// Returns a mutable reference to the Service with the given instance ID if it's present on the Accessory.
+    fn get_mut_service_by_id(&mut self, id: u64) -> Option<&mut dyn HapService> {
+        self.get_mut_services().into_iter().find(|s| s.get_id() == id)
+    }
+    /// Returns a reference to the `n`th Service of the given type, counting from 0 in the order of `get_services`, if
+    /// it's present on the Accessory.
+    fn get_nth_service(&self, hap_type: HapType, n: usize) -> Option<&dyn HapService> {
+        self.get_services()
+            .into_iter()
+            .filter(|s| s.get_type() == hap_type)
+            .nth(n)
+    }
+    /// Returns a mutable reference to the `n`th Service of the given type, counting from 0 in the order of
+    /// `get_mut_services`, if it's present on the Accessory.
+    fn get_mut_nth_service(&mut self, hap_type: HapType, n: usize) -> Option<&mut dyn HapService> {
+        self.get_mut_services()
+            .into_iter()
+            .filter(|s| s.get_type() == hap_type)
+            .nth(n)
+    }
+    /// Returns a reference to the Characteristic with the given instance ID if it's present on any Service of the
+    /// Accessory.
+    fn get_characteristic_by_id(&self, id: u64) -> Option<&dyn HapCharacteristic> {
+        self.get_services()
+            .into_iter()
+            .find_map(|s| s.get_characteristic_by_id(id))
+    }
+    /// Returns a mutable reference to the Characteristic with the given instance ID if it's present on any Service of
+    /// the Accessory.
+    fn get_mut_characteristic_by_id(&mut self, id: u64) -> Option<&mut dyn HapCharacteristic> {
+        self.get_mut_services()
+            .into_iter()
+            .find_map(|s| s.get_mut_characteristic_by_id(id))
+    }
 }
 
 serialize_trait_object!(HapAccessory);
@@ -55,6 +106,47 @@ where
     }
 }
 
+/// `AccessoryHandle` is a typed handle to an Accessory that was added to a server. The Accessory is shared with the
+/// server, so changes made through the handle are immediately visible to controllers.
+///
+/// # Examples
+///
+/// ```ignore
+/// use hap::accessory::{lightbulb::LightbulbAccessory, AccessoryInformation};
+///
+/// let lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+/// let lightbulb = server.add_accessory(lightbulb).await.unwrap();
+///
+/// lightbulb.lock().await.lightbulb.on.set_value(true).await.unwrap();
+/// ```
+#[derive(Debug)]
+pub struct AccessoryHandle<A: HapAccessory> {
+    inner: Arc<Mutex<A>>,
+}
+
+impl<A: HapAccessory + 'static> AccessoryHandle<A> {
+    /// Creates a new `AccessoryHandle` taking ownership of the Accessory.
+    pub(crate) fn new(accessory: A) -> Self {
+        AccessoryHandle {
+            inner: Arc::new(Mutex::new(accessory)),
+        }
+    }
+
+    /// Acquires the lock on the Accessory and returns a guard giving typed access to it.
+    pub async fn lock(&self) -> MutexGuard<'_, A> { self.inner.lock().await }
+
+    /// Returns a type-erased pointer to the Accessory.
+    pub(crate) fn pointer(&self) -> pointer::Accessory { self.inner.clone() }
+}
+
+impl<A: HapAccessory> Clone for AccessoryHandle<A> {
+    fn clone(&self) -> Self {
+        AccessoryHandle {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
 /// The `AccessoryInformationInformation` struct is used to store metadata about an `Accessory` and is converted to the
 /// Accessory Information Service of the `Accessory` it is passed to on its creation.
 ///
@@ -122,23 +214,22 @@ impl AccessoryInformation {
     /// Converts the `Information` struct to an Accessory Information Service.
     pub(crate) fn to_service(self, id: u64, accessory_id: u64) -> Result<AccessoryInformationService> {
         let mut i = AccessoryInformationService::new(id, accessory_id);
-        executor::block_on(i.identify.set_value(serde_json::Value::Bool(false)))?;
-        executor::block_on(i.manufacturer.set_value(serde_json::Value::String(self.manufacturer)))?;
-        executor::block_on(i.model.set_value(serde_json::Value::String(self.model)))?;
-        executor::block_on(i.name.set_value(serde_json::Value::String(self.name)))?;
-        executor::block_on(i.serial_number.set_value(serde_json::Value::String(self.serial_number)))?;
-        executor::block_on(
-            i.firmware_revision
-                .set_value(serde_json::Value::String(self.firmware_revision)),
-        )?;
+        executor::block_on(i.identify.set_value(false))?;
+        executor::block_on(i.manufacturer.set_value(self.manufacturer))?;
+        executor::block_on(i.model.set_value(self.model))?;
+        executor::block_on(i.name.set_value(self.name))?;
+        executor::block_on(i.serial_number.set_value(self.serial_number))?;
+        executor::block_on(i.firmware_revision.set_value(self.firmware_revision))?;
+        let mut next_id = id + 1 + i.get_characteristics().len() as u64;
         if let Some(v) = self.hardware_revision {
-            let mut hr = HardwareRevisionCharacteristic::new(7, accessory_id);
-            executor::block_on(hr.set_value(serde_json::Value::String(v)))?;
+            let mut hr = HardwareRevisionCharacteristic::new(next_id, accessory_id);
+            executor::block_on(hr.set_value(v))?;
             i.hardware_revision = Some(hr);
+            next_id += 1;
         }
         if let Some(v) = self.accessory_flags {
-            let mut af = AccessoryFlagsCharacteristic::new(8, accessory_id);
-            executor::block_on(af.set_value(serde_json::Value::Number(v.into())))?;
+            let mut af = AccessoryFlagsCharacteristic::new(next_id, accessory_id);
+            executor::block_on(af.set_value(v))?;
             i.accessory_flags = Some(af);
         }
         Ok(i)
@@ -158,3 +249,211 @@ impl Default for AccessoryInformation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        lightbulb::LightbulbAccessory,
+        programmable_switch::{ProgrammableSwitchAccessory, ServiceLabelNamespace},
+        *,
+    };
+    use crate::{
+        characteristic::{
+            brightness::BrightnessCharacteristic,
+            on::OnCharacteristic,
+            CharacteristicOverrides,
+            HapCharacteristic,
+        },
+        service::{lightbulb::LightbulbService, HapService},
+    };
+
+    #[derive(HapService)]
+    struct DimmerService {
+        id: u64,
+        hap_type: HapType,
+        hidden: bool,
+        primary: bool,
+        linked_services: Vec<u64>,
+
+        #[characteristic]
+        on: OnCharacteristic,
+        #[characteristic]
+        brightness: Option<BrightnessCharacteristic>,
+    }
+
+    #[derive(HapAccessory)]
+    struct DimmerAccessory {
+        id: u64,
+
+        #[service]
+        accessory_information: AccessoryInformationService,
+        #[service(linked(lightbulb))]
+        dimmer: DimmerService,
+        #[service]
+        lightbulb: Option<LightbulbService>,
+    }
+
+    #[test]
+    fn test_derive() {
+        let mut accessory = DimmerAccessory {
+            id: 1,
+            accessory_information: AccessoryInformation::default().to_service(1, 1).unwrap(),
+            dimmer: DimmerService {
+                id: 20,
+                hap_type: HapType::Lightbulb,
+                hidden: false,
+                primary: true,
+                linked_services: Vec::new(),
+                on: OnCharacteristic::new(21, 1),
+                brightness: None,
+            },
+            lightbulb: None,
+        };
+        assert_eq!(accessory.get_services().len(), 2);
+        assert_eq!(accessory.get_service(HapType::Lightbulb).unwrap().get_id(), 20);
+        assert_eq!(accessory.dimmer.get_characteristics().len(), 1);
+
+        accessory.dimmer.brightness = Some(BrightnessCharacteristic::new(22, 1));
+        accessory.lightbulb = Some(LightbulbService::new(30, 1));
+        accessory.dimmer.set_linked_services(vec![30]);
+        accessory.set_id(2);
+        assert_eq!(accessory.get_mut_services().len(), 3);
+
+        let dimmer = accessory.get_mut_service(HapType::Lightbulb).unwrap();
+        dimmer.set_hidden(true);
+        assert!(dimmer.get_mut_characteristic(HapType::Brightness).is_some());
+
+        let json = serde_json::to_value(&accessory).unwrap();
+        assert_eq!(json["aid"], 2);
+        assert_eq!(json["services"][1]["iid"], 20);
+        assert_eq!(json["services"][1]["hidden"], true);
+        assert_eq!(json["services"][1]["characteristics"].as_array().unwrap().len(), 2);
+        assert_eq!(json["services"][1]["linked"], serde_json::json!([30]));
+    }
+
+    #[test]
+    fn test_assign_ids() {
+        let mut accessory = DimmerAccessory {
+            id: 0,
+            accessory_information: AccessoryInformation::default().to_service(0, 0).unwrap(),
+            dimmer: DimmerService {
+                id: 0,
+                hap_type: HapType::Lightbulb,
+                hidden: false,
+                primary: true,
+                linked_services: Vec::new(),
+                on: OnCharacteristic::new(0, 0),
+                brightness: Some(BrightnessCharacteristic::new(0, 0)),
+            },
+            lightbulb: Some(LightbulbService::new(0, 0)),
+        };
+        accessory.assign_ids(3);
+
+        let information_count = accessory.accessory_information.get_characteristics().len() as u64;
+        let dimmer_id = 1 + information_count + 1;
+        let lightbulb_id = dimmer_id + 3;
+        assert_eq!(accessory.get_id(), 3);
+        assert_eq!(accessory.accessory_information.get_id(), 1);
+        assert_eq!(accessory.dimmer.get_id(), dimmer_id);
+        assert_eq!(accessory.dimmer.on.get_id(), dimmer_id + 1);
+        assert_eq!(accessory.dimmer.brightness.as_ref().unwrap().get_id(), dimmer_id + 2);
+        assert_eq!(
+            accessory.get_nth_service(HapType::Lightbulb, 1).unwrap().get_id(),
+            lightbulb_id
+        );
+        assert!(accessory.get_characteristic_by_id(lightbulb_id + 1).is_some());
+        assert_eq!(accessory.dimmer.get_linked_services(), vec![lightbulb_id]);
+
+        // the links follow the Services when they're renumbered
+        accessory.accessory_information = AccessoryInformation {
+            hardware_revision: Some("1.0".into()),
+            ..Default::default()
+        }
+        .to_service(0, 0)
+        .unwrap();
+        accessory.assign_ids(3);
+        assert_eq!(accessory.dimmer.get_linked_services(), vec![lightbulb_id + 1]);
+        let json = serde_json::to_value(&accessory).unwrap();
+        assert_eq!(json["services"][1]["linked"], serde_json::json!([lightbulb_id + 1]));
+
+        accessory.lightbulb = None;
+        accessory.assign_ids(3);
+        assert!(accessory.dimmer.get_linked_services().is_empty());
+    }
+
+    #[test]
+    fn test_builder() {
+        let lightbulb = LightbulbAccessory::builder(AccessoryInformation::default())
+            .with_hue()
+            .with_brightness()
+            .with_overrides(HapType::Brightness, CharacteristicOverrides {
+                max_value: Some(80.into()),
+                ..Default::default()
+            })
+            .build(2)
+            .unwrap();
+
+        let required_len = LightbulbService::new(0, 0).get_characteristics().len() as u64;
+        let service_id = lightbulb.lightbulb.get_id();
+        assert!(lightbulb.lightbulb.get_primary());
+        assert_eq!(
+            lightbulb.lightbulb.brightness.as_ref().unwrap().get_id(),
+            service_id + 1 + required_len
+        );
+        assert_eq!(
+            lightbulb.lightbulb.hue.as_ref().unwrap().get_id(),
+            service_id + 2 + required_len
+        );
+        assert!(lightbulb.lightbulb.saturation.is_none());
+        assert_eq!(
+            lightbulb.lightbulb.brightness.as_ref().unwrap().get_max_value(),
+            Some(serde_json::json!(80))
+        );
+
+        // Saturation isn't enabled, so it can't be overridden
+        assert!(LightbulbService::builder()
+            .with_overrides(HapType::Saturation, CharacteristicOverrides::default())
+            .build(1, 1)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_lookup() {
+        let mut accessory =
+            ProgrammableSwitchAccessory::new(1, AccessoryInformation::default(), 4, ServiceLabelNamespace::Dots)
+                .unwrap();
+        assert_eq!(accessory.get_services().len(), 6);
+
+        let third_switch = accessory
+            .get_mut_nth_service(HapType::StatelessProgrammableSwitch, 2)
+            .unwrap();
+        let third_switch_id = third_switch.get_id();
+        let index = third_switch.get_mut_characteristic(HapType::ServiceLabelIndex).unwrap();
+        assert_eq!(index.get_value().await.unwrap(), serde_json::json!(3));
+        let index_id = index.get_id();
+        assert!(accessory
+            .get_nth_service(HapType::StatelessProgrammableSwitch, 4)
+            .is_none());
+
+        assert_eq!(accessory.switches[2].get_id(), third_switch_id);
+        assert_eq!(
+            accessory
+                .get_service_by_id(third_switch_id)
+                .unwrap()
+                .get_characteristic_by_id(index_id)
+                .unwrap()
+                .get_type(),
+            HapType::ServiceLabelIndex
+        );
+        assert_eq!(
+            accessory.get_mut_characteristic_by_id(index_id).unwrap().get_type(),
+            HapType::ServiceLabelIndex
+        );
+        assert!(accessory.get_mut_service_by_id(1000).is_none());
+
+        assert!(
+            ProgrammableSwitchAccessory::new(1, AccessoryInformation::default(), 0, ServiceLabelNamespace::Dots)
+                .is_err()
+        );
+    }
+}
@@ -2,15 +2,19 @@ use std::fmt;
 
 use async_trait::async_trait;
 use erased_serde::serialize_trait_object;
-use futures::future::BoxFuture;
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    future::{self, BoxFuture, FutureExt},
+};
 use serde::{
     ser::{SerializeStruct, Serializer},
     Deserialize,
     Serialize,
 };
 use serde_json::json;
+use uuid::Uuid;
 
-use crate::{event::Event, pointer, HapType, Result};
+use crate::{event::Event, pairing::Permissions, pointer, HapType, Result};
 
 mod generated;
 
@@ -28,6 +32,7 @@ pub struct Characteristic<T: fmt::Debug + Default + Clone + Serialize + Send + S
     perms: Vec<Perm>,
     description: Option<String>,
     event_notifications: Option<bool>,
+    persistent: bool,
 
     value: T,
     unit: Option<Unit>,
@@ -40,12 +45,11 @@ pub struct Characteristic<T: fmt::Debug + Default + Clone + Serialize + Send + S
     valid_values: Option<Vec<T>>,
     valid_values_range: Option<[T; 2]>,
 
-    on_read: Option<Box<dyn OnReadFn<T>>>,
-    on_update: Option<Box<dyn OnUpdateFn<T>>>,
-    on_read_async: Option<Box<dyn OnReadFuture<T>>>,
-    on_update_async: Option<Box<dyn OnUpdateFuture<T>>>,
+    on_read: Option<Box<dyn OnReadContextFuture<T>>>,
+    on_update: Option<Box<dyn OnUpdateContextFuture<T>>>,
 
     event_emitter: Option<pointer::EventEmitter>,
+    watchers: Vec<UnboundedSender<ValueChange<T>>>,
 }
 
 impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> fmt::Debug for Characteristic<T> {
@@ -58,6 +62,7 @@ impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> fmt::Debug for C
             .field("perms", &self.perms)
             .field("description", &self.description)
             .field("event_notifications", &self.event_notifications)
+            .field("persistent", &self.persistent)
             .field("value", &self.value)
             .field("unit", &self.unit)
             .field("max_value", &self.max_value)
@@ -71,13 +76,19 @@ impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> fmt::Debug for C
     }
 }
 
-impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> Characteristic<T>
+impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync + 'static> Characteristic<T>
 where
     for<'de> T: Deserialize<'de>,
 {
     /// Returns the ID of a Characteristic.
     pub fn get_id(&self) -> u64 { self.id }
 
+    /// Sets the ID of a Characteristic.
+    pub fn set_id(&mut self, id: u64) { self.id = id; }
+
+    /// Sets the ID of the Accessory a Characteristic belongs to.
+    pub fn set_accessory_id(&mut self, accessory_id: u64) { self.accessory_id = accessory_id; }
+
     /// Returns the `HapType` of a Characteristic.
     pub fn get_type(&self) -> HapType { self.hap_type }
 
@@ -98,17 +109,28 @@ where
         self.event_notifications = event_notifications;
     }
 
+    /// Returns whether the value of a Characteristic is persisted across restarts.
+    pub fn is_persistent(&self) -> bool { self.persistent }
+
+    /// Sets whether the value of a Characteristic is persisted across restarts. The persisted value is restored when
+    /// the Accessory is added to a server.
+    pub fn set_persistent(&mut self, persistent: bool) { self.persistent = persistent; }
+
     /// Returns the value of a Characteristic.
     pub async fn get_value(&mut self) -> Result<T> {
+        self.get_value_with_context(&CallbackContext::with_origin(ChangeOrigin::Application))
+            .await
+    }
+
+    /// Returns the value of a Characteristic, passing the context of the read to the read callback. A value returned
+    /// by the read callback is stored with the origin of the read, without calling the update callback.
+    pub(crate) async fn get_value_with_context(&mut self, context: &CallbackContext) -> Result<T> {
         let mut val = None;
         if let Some(ref on_read) = self.on_read {
-            val = on_read();
-        }
-        if let Some(ref on_read_async) = self.on_read_async {
-            val = on_read_async().await;
+            val = on_read(context.clone()).await?;
         }
         if let Some(v) = val {
-            self.set_value(v).await?;
+            self.store_value(v, context.origin).await;
         }
 
         Ok(self.value.clone())
@@ -116,6 +138,17 @@ where
 
     /// Sets the value of a Characteristic.
     pub async fn set_value(&mut self, val: T) -> Result<()> {
+        self.set_value_with_origin(val, ChangeOrigin::Application).await
+    }
+
+    /// Sets the value of a Characteristic, recording where the change originated from.
+    pub(crate) async fn set_value_with_origin(&mut self, val: T, origin: ChangeOrigin) -> Result<()> {
+        self.set_value_with_context(val, &CallbackContext::with_origin(origin))
+            .await
+    }
+
+    /// Sets the value of a Characteristic, passing the context of the update to the update callback.
+    pub(crate) async fn set_value_with_context(&mut self, val: T, context: &CallbackContext) -> Result<()> {
         // TODO: check for min/max on types implementing PartialOrd
         // if let Some(ref max) = self.inner.try_borrow()?.max_value {
         //     if &val > max {
@@ -128,31 +161,36 @@ where
         //     }
         // }
 
-        let old_val = self.value.clone();
         if let Some(ref on_update) = self.on_update {
-            on_update(&old_val, &val);
-        }
-        if let Some(ref on_update_async) = self.on_update_async {
-            on_update_async(old_val, val.clone()).await;
+            on_update(self.value.clone(), val.clone(), context.clone()).await?;
         }
 
-        if self.event_notifications == Some(true) {
-            if let Some(ref event_emitter) = self.event_emitter {
-                event_emitter
-                    .lock()
-                    .await
-                    .emit(&Event::CharacteristicValueChanged {
-                        aid: self.accessory_id,
-                        iid: self.id,
-                        value: json!(&val),
-                    })
-                    .await;
-            }
+        self.store_value(val, context.origin).await;
+
+        Ok(())
+    }
+
+    /// Stores the value of a Characteristic, emitting an event and notifying any watchers of the change.
+    async fn store_value(&mut self, val: T, origin: ChangeOrigin) {
+        if let Some(ref event_emitter) = self.event_emitter {
+            event_emitter.lock().await.emit(&Event::CharacteristicValueChanged {
+                aid: self.accessory_id,
+                iid: self.id,
+                value: json!(&val),
+                origin,
+            });
         }
 
-        self.value = val;
+        self.watchers.retain(|watcher| {
+            watcher
+                .unbounded_send(ValueChange {
+                    value: val.clone(),
+                    origin,
+                })
+                .is_ok()
+        });
 
-        Ok(())
+        self.value = val;
     }
 
     /// Returns the `Unit` of a Characteristic.
@@ -181,33 +219,69 @@ where
 
     /// Sets a callback function on a characteristic that is called every time a controller attempts to read its value.
     /// Returning a `Some(T)` from this function changes the value of the `Characteristic` before the Controller reads
-    /// it so the Controller reads the new value.
+    /// it so the Controller reads the new value. Replaces any read callback set before.
     pub fn on_read(&mut self, f: Option<impl OnReadFn<T>>) {
-        self.on_read = f.map(|f| Box::new(f) as Box<dyn OnReadFn<T>>);
+        self.on_read_with_context(f.map(|f| move |_: CallbackContext| future::ready(CallbackResult::Ok(f())).boxed()));
     }
 
     /// Sets a callback function on a characteristic that is called every time a controller attempts to update its
     /// value. The first argument is a reference to the current value of the characteristic and the second argument is a
-    /// reference to the value the controller attempts to change the characteristic's to.
+    /// reference to the value the controller attempts to change the characteristic's to. Replaces any update callback
+    /// set before.
     pub fn on_update(&mut self, f: Option<impl OnUpdateFn<T>>) {
-        self.on_update = f.map(|f| Box::new(f) as Box<dyn OnUpdateFn<T>>);
+        self.on_update_with_context(f.map(|f| {
+            move |old_val: T, new_val: T, _: CallbackContext| {
+                f(&old_val, &new_val);
+                future::ready(CallbackResult::Ok(())).boxed()
+            }
+        }));
     }
 
     /// Sets an async callback function on a characteristic that is driven to completion by the async runtime driving
     /// the HAP server every time a controller attempts to read its value. Returning a `Some(T)` from this function
     /// changes the value of the characteristic before the controller reads it so the controller reads the new value.
+    /// Replaces any read callback set before.
     pub fn on_read_async(&mut self, f: Option<impl OnReadFuture<T>>) {
-        self.on_read_async = f.map(|f| Box::new(f) as Box<dyn OnReadFuture<T>>);
+        self.on_read_with_context(f.map(|f| move |_: CallbackContext| f().map(CallbackResult::Ok).boxed()));
     }
 
     /// Sets an async callback function on a characteristic that is driven to completion by the async runtime driving
     /// the HAP server every time a controller attempts to update its value. The first argument is a reference to the
     /// current value of the characteristic and the second argument is a reference to the value the controller attempts
-    /// to change the characteristic's to.
+    /// to change the characteristic's to. Replaces any update callback set before.
     pub fn on_update_async(&mut self, f: Option<impl OnUpdateFuture<T>>) {
-        self.on_update_async = f.map(|f| Box::new(f) as Box<dyn OnUpdateFuture<T>>);
+        self.on_update_with_context(f.map(|f| {
+            move |old_val: T, new_val: T, _: CallbackContext| f(old_val, new_val).map(CallbackResult::Ok).boxed()
+        }));
+    }
+
+    /// Sets a fallible async callback function on a characteristic that is driven to completion by the async runtime
+    /// driving the HAP server every time its value is read, by a controller or by `get_value`, receiving the
+    /// `CallbackContext` of the read. Returning an `Err` from this function fails the read. If a controller made the
+    /// read, it is sent the HAP status matching the `CallbackError`. Replaces any read callback set before.
+    pub fn on_read_with_context(&mut self, f: Option<impl OnReadContextFuture<T>>) {
+        self.on_read = f.map(|f| Box::new(f) as Box<dyn OnReadContextFuture<T>>);
+    }
+
+    /// Sets a fallible async callback function on a characteristic that is driven to completion by the async runtime
+    /// driving the HAP server every time its value is updated, by a controller or by `set_value`, receiving the
+    /// `CallbackContext` of the update. Returning an `Err` from this function rejects the update, leaving the value of
+    /// the characteristic unchanged, e.g. with `CallbackError::InsufficientPrivileges` for a controller that isn't
+    /// allowed to change the value. Replaces any update callback set before.
+    pub fn on_update_with_context(&mut self, f: Option<impl OnUpdateContextFuture<T>>) {
+        self.on_update = f.map(|f| Box::new(f) as Box<dyn OnUpdateContextFuture<T>>);
+    }
+
+    /// Returns a stream yielding every change to the value of the Characteristic. Dropping the stream stops watching.
+    pub fn watch(&mut self) -> ValueStream<T> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.watchers.push(sender);
+        receiver
     }
 
+    /// Sets the value of a Characteristic without calling any callbacks, notifying any watchers or emitting an event.
+    pub(crate) fn restore_value(&mut self, val: T) { self.value = val; }
+
     /// Sets a `hap::event::pointer::EventEmitter` on the Characteristic.
     pub(crate) fn set_event_emitter(&mut self, event_emitter: Option<pointer::EventEmitter>) {
         self.event_emitter = event_emitter;
@@ -259,6 +333,141 @@ impl<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> Serialize for Ch
     }
 }
 
+/// Origin of a change to the value of a `Characteristic`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChangeOrigin {
+    /// The value was changed by a controller.
+    Controller,
+    /// The value was changed by the application.
+    Application,
+}
+
+/// Error returned by a fallible callback of a `Characteristic`, reported to the controller as the HAP status of the
+/// same name.
+#[derive(Debug, Copy, Clone, PartialEq, thiserror::Error)]
+pub enum CallbackError {
+    /// The device backing the Characteristic is unreachable or didn't respond.
+    #[error("Communication with the device failed.")]
+    ServiceCommunicationFailure,
+    /// The device is busy and can't handle the request right now.
+    #[error("The device is busy.")]
+    ResourceBusy,
+    /// The device refused the requested value.
+    #[error("The device refused the value.")]
+    InvalidValueInRequest,
+    /// The controller isn't allowed to perform the request.
+    #[error("The controller isn't allowed to perform the request.")]
+    InsufficientPrivileges,
+}
+
+/// Result type of fallible `Characteristic` callbacks.
+pub type CallbackResult<T> = std::result::Result<T, CallbackError>;
+
+/// Context of a read or an update of a `Characteristic`, passed to the contextual callbacks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallbackContext {
+    /// Pairing ID of the controller making the request. `None` if the request didn't come from a verified controller.
+    pub controller_id: Option<Uuid>,
+    /// Permissions of the pairing of the controller making the request.
+    pub permissions: Option<Permissions>,
+    /// Specifies if the controller relayed the write through a home hub, as indicated by the `remote` flag.
+    pub remote: bool,
+    /// Origin of the request.
+    pub origin: ChangeOrigin,
+}
+
+impl CallbackContext {
+    /// Creates a new context of a request that didn't come from a verified controller.
+    pub fn with_origin(origin: ChangeOrigin) -> Self {
+        CallbackContext {
+            controller_id: None,
+            permissions: None,
+            remote: false,
+            origin,
+        }
+    }
+
+    /// Returns `true` if the request came from a controller with admin permissions.
+    pub fn is_admin(&self) -> bool { self.permissions == Some(Permissions::Admin) }
+}
+
+/// A change to the value of a `Characteristic`.
+#[derive(Debug, Clone)]
+pub struct ValueChange<T> {
+    /// The new value of the Characteristic.
+    pub value: T,
+    /// Origin of the change.
+    pub origin: ChangeOrigin,
+}
+
+/// A stream of changes to the value of a `Characteristic`, as returned by `watch()`.
+pub type ValueStream<T> = UnboundedReceiver<ValueChange<T>>;
+
+/// A change to the value of a `Characteristic` on any Accessory of a server.
+#[derive(Debug, Clone)]
+pub struct CharacteristicChange {
+    /// ID of the Accessory the Characteristic belongs to.
+    pub aid: u64,
+    /// ID of the Characteristic.
+    pub iid: u64,
+    /// The new value of the Characteristic.
+    pub value: serde_json::Value,
+    /// Origin of the change.
+    pub origin: ChangeOrigin,
+}
+
+/// Metadata overrides for a `Characteristic`, replacing the defaults of its HAP definition.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+///     characteristic::CharacteristicOverrides,
+///     HapType,
+/// };
+///
+/// let lightbulb = LightbulbAccessory::builder(AccessoryInformation::default())
+///     .with_brightness()
+///     .with_overrides(HapType::Brightness, CharacteristicOverrides {
+///         max_value: Some(80.into()),
+///         ..Default::default()
+///     })
+///     .build(1)
+///     .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct CharacteristicOverrides {
+    /// Description of the Characteristic.
+    pub description: Option<String>,
+    /// Overrides the maximum value of the Characteristic.
+    pub max_value: Option<serde_json::Value>,
+    /// Overrides the minimum value of the Characteristic.
+    pub min_value: Option<serde_json::Value>,
+    /// Overrides the step value of the Characteristic.
+    pub step_value: Option<serde_json::Value>,
+}
+
+impl CharacteristicOverrides {
+    /// Applies the set overrides to a Characteristic, leaving the unset ones at their current value.
+    pub fn apply(&self, characteristic: &mut dyn HapCharacteristic) -> Result<()> {
+        if self.description.is_some() {
+            characteristic.set_description(self.description.clone());
+        }
+        if self.max_value.is_some() {
+            characteristic.set_max_value(self.max_value.clone())?;
+        }
+        if self.min_value.is_some() {
+            characteristic.set_min_value(self.min_value.clone())?;
+        }
+        if self.step_value.is_some() {
+            characteristic.set_step_value(self.step_value.clone())?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Permission of a `Characteristic`.
 #[derive(Debug, Copy, Clone, Serialize, PartialEq)]
 pub enum Perm {
@@ -325,28 +534,52 @@ impl Default for Format {
 pub trait HapCharacteristic: HapCharacteristicSetup + erased_serde::Serialize + Send + Sync {
     /// Returns the ID of a Characteristic.
     fn get_id(&self) -> u64;
+    /// Sets the ID of a Characteristic.
+    fn set_id(&mut self, id: u64);
+    /// Sets the ID of the Accessory a Characteristic belongs to.
+    fn set_accessory_id(&mut self, accessory_id: u64);
     /// Returns the `HapType` of a Characteristic.
     fn get_type(&self) -> HapType;
     /// Returns the `Format` of a Characteristic.
     fn get_format(&self) -> Format;
     /// Returns the `Perm`s of a Characteristic.
     fn get_perms(&self) -> Vec<Perm>;
+    /// Sets the description of a Characteristic.
+    fn set_description(&mut self, description: Option<String>);
     /// Returns the event notifications value of a Characteristic.
     fn get_event_notifications(&self) -> Option<bool>;
     /// Sets the event notifications value of a Characteristic.
     fn set_event_notifications(&mut self, event_notifications: Option<bool>);
+    /// Returns whether the value of a Characteristic is persisted across restarts.
+    fn is_persistent(&self) -> bool;
+    /// Sets whether the value of a Characteristic is persisted across restarts.
+    fn set_persistent(&mut self, persistent: bool);
     /// Returns the value of a Characteristic.
     async fn get_value(&mut self) -> Result<serde_json::Value>;
     /// Sets the value of a Characteristic.
     async fn set_value(&mut self, value: serde_json::Value) -> Result<()>;
+    /// Sets the value of a Characteristic, recording where the change originated from.
+    async fn set_value_with_origin(&mut self, value: serde_json::Value, origin: ChangeOrigin) -> Result<()>;
+    /// Returns the value of a Characteristic, passing the context of the read to the contextual callbacks.
+    async fn get_value_with_context(&mut self, context: &CallbackContext) -> Result<serde_json::Value>;
+    /// Sets the value of a Characteristic, passing the context of the update to the contextual callbacks.
+    async fn set_value_with_context(&mut self, value: serde_json::Value, context: &CallbackContext) -> Result<()>;
+    /// Sets a persisted value of a Characteristic without calling any callbacks or emitting an event.
+    fn restore_value(&mut self, value: serde_json::Value) -> Result<()>;
     /// Returns the `Unit` of a Characteristic.
     fn get_unit(&self) -> Option<Unit>;
     /// Returns the maximum value of a Characteristic.
     fn get_max_value(&self) -> Option<serde_json::Value>;
+    /// Sets the maximum value of a Characteristic.
+    fn set_max_value(&mut self, max_value: Option<serde_json::Value>) -> Result<()>;
     /// Returns the minimum value of a Characteristic.
     fn get_min_value(&self) -> Option<serde_json::Value>;
+    /// Sets the minimum value of a Characteristic.
+    fn set_min_value(&mut self, min_value: Option<serde_json::Value>) -> Result<()>;
     /// Returns the step value of a Characteristic.
     fn get_step_value(&self) -> Option<serde_json::Value>;
+    /// Sets the step value of a Characteristic.
+    fn set_step_value(&mut self, step_value: Option<serde_json::Value>) -> Result<()>;
     /// Returns the maximum length of a Characteristic.
     fn get_max_len(&self) -> Option<u16>;
 }
@@ -385,6 +618,24 @@ impl<F, T: Default + Clone + Serialize + Send + Sync> OnUpdateFuture<T> for F wh
 {
 }
 
+pub trait OnReadContextFuture<T: Default + Clone + Serialize + Send + Sync>:
+    Fn(CallbackContext) -> BoxFuture<'static, CallbackResult<Option<T>>> + 'static + Send + Sync
+{
+}
+impl<F, T: Default + Clone + Serialize + Send + Sync> OnReadContextFuture<T> for F where
+    F: Fn(CallbackContext) -> BoxFuture<'static, CallbackResult<Option<T>>> + 'static + Send + Sync
+{
+}
+
+pub trait OnUpdateContextFuture<T: Default + Clone + Serialize + Send + Sync>:
+    Fn(T, T, CallbackContext) -> BoxFuture<'static, CallbackResult<()>> + 'static + Send + Sync
+{
+}
+impl<F, T: Default + Clone + Serialize + Send + Sync> OnUpdateContextFuture<T> for F where
+    F: Fn(T, T, CallbackContext) -> BoxFuture<'static, CallbackResult<()>> + 'static + Send + Sync
+{
+}
+
 // Fn() -> impl Future<Output = Option<T>>
 // Fn(&T, &T) -> Future<Output = ()>
 
@@ -411,9 +662,28 @@ pub trait AsyncCharacteristicCallbacks<T: fmt::Debug + Default + Clone + Seriali
     fn on_update_async(&mut self, f: Option<impl OnUpdateFuture<T>>);
 }
 
+pub trait ContextCharacteristicCallbacks<T: fmt::Debug + Default + Clone + Serialize + Send + Sync> {
+    /// Sets a fallible async callback function on a characteristic that is driven to completion by the async runtime
+    /// driving the HAP server every time its value is read, receiving the `CallbackContext` of the read. Returning an
+    /// `Err` from this function fails the read.
+    fn on_read_with_context(&mut self, f: Option<impl OnReadContextFuture<T>>);
+    /// Sets a fallible async callback function on a characteristic that is driven to completion by the async runtime
+    /// driving the HAP server every time its value is updated, receiving the `CallbackContext` of the update.
+    /// Returning an `Err` from this function rejects the update.
+    fn on_update_with_context(&mut self, f: Option<impl OnUpdateContextFuture<T>>);
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use futures::future::FutureExt;
+
     use super::*;
+    use crate::Error;
 
     #[test]
     fn test_json_serialization() {
@@ -425,6 +695,7 @@ mod tests {
             perms: vec![Perm::PairedRead, Perm::Events],
             description: Some("Acme Tilt Angle".into()),
             event_notifications: Some(true),
+            persistent: false,
 
             value: 123,
             unit: Some(Unit::ArcDegrees),
@@ -439,12 +710,142 @@ mod tests {
 
             on_read: None,
             on_update: None,
-            on_read_async: None,
-            on_update_async: None,
 
             event_emitter: None,
+            watchers: Vec::new(),
         };
         let json = serde_json::to_string(&characteristic).unwrap();
         assert_eq!(json, "{\"iid\":1,\"type\":\"C1\",\"format\":\"uint16\",\"perms\":[\"pr\",\"ev\"],\"description\":\"Acme Tilt Angle\",\"ev\":true,\"value\":123,\"unit\":\"arcdegrees\",\"maxValue\":360,\"minValue\":0,\"minStep\":1,\"valid-values-range\":[0,360]}".to_string());
     }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let mut characteristic = Characteristic::<u16>::default();
+        let mut stream = characteristic.watch();
+
+        characteristic.set_value(1).await.unwrap();
+        characteristic
+            .set_value_with_origin(2, ChangeOrigin::Controller)
+            .await
+            .unwrap();
+
+        let change = stream.try_next().unwrap().unwrap();
+        assert_eq!(change.value, 1);
+        assert_eq!(change.origin, ChangeOrigin::Application);
+        let change = stream.try_next().unwrap().unwrap();
+        assert_eq!(change.value, 2);
+        assert_eq!(change.origin, ChangeOrigin::Controller);
+
+        drop(stream);
+        characteristic.set_value(3).await.unwrap();
+        assert!(characteristic.watchers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fallible_callbacks() {
+        let mut characteristic = Characteristic::<u16>::default();
+        let mut stream = characteristic.watch();
+        characteristic.on_update_with_context(Some(|_, new, _| {
+            async move {
+                if new > 100 {
+                    Err(CallbackError::InvalidValueInRequest)
+                } else {
+                    Ok(())
+                }
+            }
+            .boxed()
+        }));
+
+        characteristic.set_value(50).await.unwrap();
+        match characteristic.set_value(150).await {
+            Err(Error::Callback(CallbackError::InvalidValueInRequest)) => {},
+            _ => panic!("expected the update to be rejected"),
+        }
+        assert_eq!(characteristic.get_value().await.unwrap(), 50);
+        assert_eq!(stream.try_next().unwrap().unwrap().value, 50);
+        assert!(stream.try_next().is_err());
+
+        characteristic.on_update_with_context(Some(|_, _, _| async { Err(CallbackError::ResourceBusy) }.boxed()));
+        match characteristic.set_value(60).await {
+            Err(Error::Callback(CallbackError::ResourceBusy)) => {},
+            _ => panic!("expected the update to be rejected"),
+        }
+
+        characteristic.on_read_with_context(Some(|_| {
+            async { Err(CallbackError::ServiceCommunicationFailure) }.boxed()
+        }));
+        match characteristic.get_value().await {
+            Err(Error::Callback(CallbackError::ServiceCommunicationFailure)) => {},
+            _ => panic!("expected the read to fail"),
+        }
+        assert_eq!(characteristic.value, 50);
+    }
+
+    #[tokio::test]
+    async fn test_callback_context() {
+        let mut characteristic = Characteristic::<bool>::default();
+        characteristic.on_update_with_context(Some(|_, _, context: CallbackContext| {
+            async move {
+                match context.origin {
+                    ChangeOrigin::Controller if !context.is_admin() || context.remote =>
+                        Err(CallbackError::InsufficientPrivileges),
+                    _ => Ok(()),
+                }
+            }
+            .boxed()
+        }));
+        characteristic.on_read_with_context(Some(|context: CallbackContext| {
+            async move { Ok(Some(context.controller_id.is_some())) }.boxed()
+        }));
+
+        let user = CallbackContext {
+            controller_id: Some(Uuid::new_v4()),
+            permissions: Some(Permissions::User),
+            ..CallbackContext::with_origin(ChangeOrigin::Controller)
+        };
+        let admin = CallbackContext {
+            permissions: Some(Permissions::Admin),
+            ..user.clone()
+        };
+        let remote_admin = CallbackContext {
+            remote: true,
+            ..admin.clone()
+        };
+
+        match characteristic.set_value_with_context(true, &user).await {
+            Err(Error::Callback(CallbackError::InsufficientPrivileges)) => {},
+            _ => panic!("expected the update to be rejected"),
+        }
+        assert!(characteristic
+            .set_value_with_context(true, &remote_admin)
+            .await
+            .is_err());
+        characteristic.set_value_with_context(true, &admin).await.unwrap();
+        characteristic.set_value(false).await.unwrap();
+
+        // the value returned by the read callback is stored without calling the update callback
+        let mut stream = characteristic.watch();
+        assert!(characteristic.get_value_with_context(&user).await.unwrap());
+        assert!(characteristic.value);
+        let change = stream.try_next().unwrap().unwrap();
+        assert!(change.value);
+        assert_eq!(change.origin, ChangeOrigin::Controller);
+        assert!(!characteristic.get_value().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_read_skips_update_callback() {
+        let updates = Arc::new(AtomicUsize::new(0));
+        let mut characteristic = Characteristic::<u16>::default();
+        let counter = updates.clone();
+        characteristic.on_update(Some(move |_: &u16, _: &u16| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        characteristic.on_read(Some(|| Some(42)));
+
+        assert_eq!(characteristic.get_value().await.unwrap(), 42);
+        assert_eq!(updates.load(Ordering::SeqCst), 0);
+        characteristic.set_value(7).await.unwrap();
+        assert_eq!(updates.load(Ordering::SeqCst), 1);
+    }
 }
@@ -5,7 +5,7 @@ use eui48::MacAddress;
 use rand::{rngs::OsRng, Rng};
 use serde::{Deserialize, Serialize};
 
-use crate::{accessory::AccessoryCategory, BonjourFeatureFlag, BonjourStatusFlag, Pin};
+use crate::{accessory::AccessoryCategory, BonjourFeatureFlag, BonjourStatusFlag, Pin, SetupVerifier};
 
 /// The `Config` struct is used to store configuration options for the HomeKit Accessory Server.
 ///
@@ -15,7 +15,7 @@ use crate::{accessory::AccessoryCategory, BonjourFeatureFlag, BonjourStatusFlag,
 /// use hap::{accessory::AccessoryCategory, Config};
 ///
 /// let config = Config {
-///     pin: Pin::from_str("11122333").unwrap(),
+///     pin: Some(Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap()),
 ///     name: "Acme Outlet".into(),
 ///     category: AccessoryCategory::Outlet,
 ///     max_peers: Some(32),
@@ -41,7 +41,14 @@ pub struct Config {
     /// - `77777777`
     /// - `88888888`
     /// - `99999999`
-    pub pin: Pin,
+    ///
+    /// Can be set to `None` if a `setup_verifier` is provisioned instead.
+    pub pin: Option<Pin>,
+    /// SRP salt and verifier computed from the pin. If set, Pair Setup is run from the verifier and `pin` is ignored,
+    /// so the plaintext pin doesn't have to be stored. Use [`Config::provision_setup_verifier`] to replace the pin
+    /// with its verifier.
+    #[serde(default)]
+    pub setup_verifier: Option<SetupVerifier>,
     /// Model name of the accessory. E.g. "Acme Lightbulb".
     pub name: String,
     /// Device ID of the accessory. Generated randomly if not specified. This value is also used as the accessory's
@@ -52,6 +59,10 @@ pub struct Config {
     /// Current configuration number. Is updated when an accessory, service, or characteristic is added or removed on
     /// the accessory server. Accessories must increment the config number after a firmware update.
     pub configuration_number: u64, // c#
+    /// Hash of the accessory database structure the current configuration number was issued for. Is used to detect
+    /// changes to the accessory database across restarts.
+    #[serde(default)]
+    pub configuration_hash: Option<u64>,
     /// Current state number. This must have a value of `1`.
     pub state_number: u8, // s#
     /// Accessory category. Indicates the category that best describes the primary function of the accessory.
@@ -65,9 +76,30 @@ pub struct Config {
     pub feature_flag: BonjourFeatureFlag, // ff
     /// Optional maximum number of paired controllers.
     pub max_peers: Option<usize>,
+    /// Whether to persist the values of all writable characteristics across restarts, in addition to the ones marked
+    /// as persistent. Defaults to `false`.
+    #[serde(default)]
+    pub persist_writable_values: bool,
 }
 
 impl Config {
+    /// Replaces the plaintext pin with a freshly salted `SetupVerifier` computed from it. Does nothing if there's no
+    /// pin.
+    pub fn provision_setup_verifier(&mut self) {
+        if let Some(pin) = self.pin.take() {
+            self.setup_verifier = Some(pin.setup_verifier());
+        }
+    }
+
+    /// Returns the `SetupVerifier` Pair Setup is run from, computing one from the pin if none is provisioned.
+    pub(crate) fn current_setup_verifier(&self) -> Option<SetupVerifier> {
+        match (&self.setup_verifier, &self.pin) {
+            (Some(setup_verifier), _) => Some(setup_verifier.clone()),
+            (None, Some(pin)) => Some(pin.setup_verifier()),
+            (None, None) => None,
+        }
+    }
+
     pub(crate) fn txt_records(&self) -> [String; 8] {
         [
             format!("md={}", self.name),
@@ -86,28 +118,31 @@ impl Default for Config {
     fn default() -> Config {
         Config {
             socket_addr: get_current_ipv4(),
-            pin: Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap(),
+            pin: Some(Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap()),
             name: "Accessory".into(),
             device_id: generate_random_mac_address(),
             device_ed25519_keypair: generate_ed25519_keypair(),
+            setup_verifier: None,
             configuration_number: 1,
+            configuration_hash: None,
             state_number: 1,
             category: AccessoryCategory::Unknown,
             protocol_version: "1.0".into(),
             status_flag: BonjourStatusFlag::NotPaired,
             feature_flag: BonjourFeatureFlag::Zero,
             max_peers: None,
+            persist_writable_values: false,
         }
     }
 }
 
-fn generate_random_mac_address() -> MacAddress {
+pub(crate) fn generate_random_mac_address() -> MacAddress {
     let mut csprng = OsRng {};
     let eui = csprng.gen::<[u8; 6]>();
     MacAddress::new(eui)
 }
 
-fn generate_ed25519_keypair() -> Ed25519Keypair {
+pub(crate) fn generate_ed25519_keypair() -> Ed25519Keypair {
     let mut csprng = OsRng {};
     Ed25519Keypair::generate(&mut csprng)
 }
@@ -2,7 +2,10 @@ use std::{io, num, str, sync::mpsc};
 
 use thiserror::Error;
 
-use crate::characteristic::Format;
+use crate::{
+    characteristic::{CallbackError, Format},
+    HapType,
+};
 
 /// HAP error type.
 #[derive(Debug, Error)]
@@ -24,6 +27,48 @@ pub enum Error {
     AccessoryNotFound,
     #[error("The provided accessory was already added to the server.")]
     DuplicateAccessory,
+    #[error("The accessory ID 0 is invalid. Accessory IDs start at 1.")]
+    InvalidAccessoryId,
+    #[error("The server already holds the maximum number of 150 accessories.")]
+    MaxAccessories,
+    #[error("The provided accessory is missing an Accessory Information Service.")]
+    MissingAccessoryInformation,
+    #[error("The instance ID {0} is used more than once on the provided accessory.")]
+    DuplicateInstanceId(u64),
+    #[error("The server already holds the maximum number of 16 sessions.")]
+    MaxSessions,
+    #[error("The selected session is not active on the server.")]
+    SessionNotFound,
+    #[error("The server can't accept any more pairings.")]
+    MaxPeers,
+    #[error("The provided public key is invalid or doesn't match the one of the existing pairing.")]
+    InvalidPublicKey,
+    #[error("The last admin pairing can't be demoted to a user.")]
+    LastAdmin,
+    #[error("The storage was written with the unsupported schema version {0}.")]
+    UnsupportedStorageVersion(u32),
+    #[error("The encryption key couldn't be read from {0}.")]
+    EncryptionKeyUnavailable(String),
+    #[error("The storage namespace or key {0:?} is invalid.")]
+    InvalidStorageKey(String),
+    #[error("The HAP type {0:?} is unknown.")]
+    UnknownHapType(String),
+    #[error("The characteristic type {1:?} can't be added to the service type {0:?}.")]
+    UnsupportedCharacteristic(HapType, HapType),
+    #[error("The linked service {0} doesn't refer to a service of the accessory.")]
+    InvalidLinkedService(u64),
+    #[error("There's no service at position {0} of the accessory.")]
+    InvalidServicePosition(usize),
+    #[error("A programmable switch needs at least one button.")]
+    NoButtons,
+    #[error("A characteristic callback failed: {0}")]
+    Callback(#[from] CallbackError),
+    #[error("The backup bundle is malformed or its signature is invalid.")]
+    InvalidBackup,
+    #[error("The backup bundle is encrypted and requires a passphrase.")]
+    BackupPassphraseRequired,
+    #[error("The backup bundle isn't encrypted and requires the public key of the device that exported it.")]
+    BackupPublicKeyRequired,
     #[error(
         "The provided value has an invalid data type for the characteristic. The characteristic's format is {0:?}."
     )]
@@ -52,6 +97,9 @@ pub enum Error {
     ParseInt(#[from] num::ParseIntError),
     #[error("MPSC Send Error: {0}")]
     MpscSend(#[from] mpsc::SendError<()>),
+    #[cfg(feature = "sled-storage")]
+    #[error("Sled Error: {0}")]
+    Sled(#[from] sled::Error),
 }
 
 impl From<aead::Error> for Error {
@@ -1,32 +1,205 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, net::SocketAddr};
 
-use futures::future::{join_all, BoxFuture};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    future::BoxFuture,
+    stream::StreamExt,
+};
 use log::debug;
 use serde_json::Value;
 use uuid::Uuid;
 
-#[derive(Debug)]
+use crate::{characteristic::ChangeOrigin, tlv};
+
+/// An event emitted by a HAP server.
+#[derive(Debug, Clone)]
 pub enum Event {
+    /// A controller was paired.
     ControllerPaired { id: Uuid },
+    /// A controller was unpaired.
     ControllerUnpaired { id: Uuid },
-    CharacteristicValueChanged { aid: u64, iid: u64, value: Value },
+    /// A pair setup attempt failed.
+    PairSetupFailed { reason: PairSetupFailure },
+    /// A controller verified a session.
+    SessionVerified { controller_id: Uuid, peer_addr: SocketAddr },
+    /// A connection was closed. `controller_id` is `None` if the connection was never verified.
+    SessionClosed {
+        controller_id: Option<Uuid>,
+        peer_addr: SocketAddr,
+    },
+    /// A controller asked an Accessory to identify itself.
+    IdentifyRequested { aid: u64 },
+    /// The value of a Characteristic changed.
+    CharacteristicValueChanged {
+        aid: u64,
+        iid: u64,
+        value: Value,
+        origin: ChangeOrigin,
+    },
+    /// An Accessory was added to the server.
+    AccessoryAdded { aid: u64 },
+    /// An Accessory was removed from the server.
+    AccessoryRemoved { aid: u64 },
+    /// The configuration number changed.
+    ConfigurationNumberChanged { configuration_number: u64 },
+}
+
+/// Reason a pair setup attempt failed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PairSetupFailure {
+    /// Generic error.
+    Unknown,
+    /// Setup code or signature verification failed.
+    Authentication,
+    /// The controller was told to wait before retrying.
+    Backoff,
+    /// The server can't accept any more pairings.
+    MaxPeers,
+    /// The server reached its maximum number of authentication attempts.
+    MaxTries,
+    /// Pair setup is unavailable, e.g. because the server is already paired.
+    Unavailable,
+    /// The server is busy with another pair setup attempt.
+    Busy,
 }
 
+impl From<tlv::Error> for PairSetupFailure {
+    fn from(err: tlv::Error) -> Self {
+        match err {
+            tlv::Error::Unknown => PairSetupFailure::Unknown,
+            tlv::Error::Authentication => PairSetupFailure::Authentication,
+            tlv::Error::Backoff => PairSetupFailure::Backoff,
+            tlv::Error::MaxPeers => PairSetupFailure::MaxPeers,
+            tlv::Error::MaxTries => PairSetupFailure::MaxTries,
+            tlv::Error::Unavailable => PairSetupFailure::Unavailable,
+            tlv::Error::Busy => PairSetupFailure::Busy,
+        }
+    }
+}
+
+/// A stream of `Event`s, as returned by `IpServer::subscribe()`. Dropping the stream ends the subscription.
+pub type EventStream = UnboundedReceiver<Event>;
+
+/// A listener added to an `EventEmitter`.
+pub type Listener = Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>;
+
+/// ID of a listener added to an `EventEmitter`, used to remove it again.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ListenerId(usize);
+
+/// An added listener. Events are queued for it and awaited one after another by a task of its own, which is spawned
+/// on the first emitted event, so listeners can be added outside of the async runtime.
+struct ListenerQueue {
+    id: ListenerId,
+    sender: UnboundedSender<Event>,
+    pending: Option<(UnboundedReceiver<Event>, Listener)>,
+}
+
+/// Dispatches `Event`s to listeners and subscribers.
 #[derive(Default)]
 pub struct EventEmitter {
-    listeners: Vec<Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>>,
+    listeners: Vec<ListenerQueue>,
+    next_listener_id: usize,
+    subscribers: Vec<UnboundedSender<Event>>,
 }
 
 impl EventEmitter {
-    pub fn new() -> EventEmitter { EventEmitter { listeners: vec![] } }
+    /// Creates a new `EventEmitter`.
+    pub fn new() -> EventEmitter { EventEmitter::default() }
 
-    pub fn add_listener(&mut self, listener: Box<dyn (Fn(&Event) -> BoxFuture<()>) + Send + Sync>) {
-        self.listeners.push(listener);
+    /// Adds a listener that is called with every emitted event and returns its ID. Events are queued for the listener,
+    /// which handles them in the order they were emitted, so a slow listener doesn't hold up the emitter.
+    pub fn add_listener(&mut self, listener: Listener) -> ListenerId {
+        let id = ListenerId(self.next_listener_id);
+        self.next_listener_id += 1;
+        let (sender, receiver) = mpsc::unbounded();
+        self.listeners.push(ListenerQueue {
+            id,
+            sender,
+            pending: Some((receiver, listener)),
+        });
+        id
     }
 
-    pub async fn emit(&self, event: &Event) {
+    /// Removes the listener with the given ID. Events that are already queued for it are still handled.
+    pub fn remove_listener(&mut self, id: ListenerId) { self.listeners.retain(|l| l.id != id); }
+
+    /// Returns a stream getting a copy of every emitted event. Events are queued on the stream, so a slow subscriber
+    /// doesn't hold up the emitter. The subscription ends when the stream is dropped.
+    pub fn subscribe(&mut self) -> EventStream {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Emits an event to all listeners and subscribers without waiting for them to handle it. Has to be called from
+    /// within the async runtime.
+    pub fn emit(&mut self, event: &Event) {
         debug!("emitting event: {:?}", event);
 
-        join_all(self.listeners.iter().map(|listener| listener(&event))).await;
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+
+        for listener in &mut self.listeners {
+            if let Some((mut receiver, listener)) = listener.pending.take() {
+                tokio::spawn(async move {
+                    while let Some(event) = receiver.next().await {
+                        listener(&event).await;
+                    }
+                });
+            }
+        }
+        self.listeners
+            .retain(|listener| listener.sender.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use futures::{channel::oneshot, future::FutureExt, lock::Mutex};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_slow_listener() {
+        let mut event_emitter = EventEmitter::new();
+        let (release_sender, release_receiver) = oneshot::channel::<()>();
+        let release_receiver = Arc::new(Mutex::new(Some(release_receiver)));
+        let handled = Arc::new(AtomicUsize::new(0));
+
+        let handled_ = handled.clone();
+        let listener_id = event_emitter.add_listener(Box::new(move |_| {
+            let release_receiver = release_receiver.clone();
+            let handled = handled_.clone();
+            async move {
+                // the first event blocks the listener until it's released
+                if let Some(receiver) = release_receiver.lock().await.take() {
+                    receiver.await.ok();
+                }
+                handled.fetch_add(1, Ordering::SeqCst);
+            }
+            .boxed()
+        }));
+        let mut stream = event_emitter.subscribe();
+
+        event_emitter.emit(&Event::AccessoryAdded { aid: 1 });
+        event_emitter.emit(&Event::AccessoryAdded { aid: 2 });
+        assert_eq!(handled.load(Ordering::SeqCst), 0);
+        assert!(matches!(stream.next().await, Some(Event::AccessoryAdded { aid: 1 })));
+
+        release_sender.send(()).unwrap();
+        while handled.load(Ordering::SeqCst) < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        event_emitter.remove_listener(listener_id);
+        event_emitter.emit(&Event::AccessoryAdded { aid: 3 });
+        tokio::task::yield_now().await;
+        assert_eq!(handled.load(Ordering::SeqCst), 2);
     }
 }
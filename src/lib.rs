@@ -1,6 +1,7 @@
+extern crate self as hap;
+
 mod config;
 mod error;
-mod event;
 mod hap_type;
 mod pin;
 mod pointer;
@@ -9,24 +10,27 @@ mod transport;
 
 pub mod accessory;
 pub mod characteristic;
+pub mod event;
 pub mod service;
 
 pub mod pairing;
 pub mod server;
+pub mod session;
 pub mod storage;
 
 pub use crate::{
     config::Config,
     error::Error,
     hap_type::HapType,
-    pin::Pin,
+    pin::{Pin, SetupVerifier},
     transport::bonjour::{BonjourFeatureFlag, BonjourStatusFlag},
 };
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub use ed25519_dalek::Keypair as Ed25519Keypair;
+pub use ed25519_dalek::{Keypair as Ed25519Keypair, PublicKey as Ed25519PublicKey};
 pub use eui48::MacAddress;
 pub use futures;
+pub use serde;
 pub use serde_json;
 pub use tokio;
@@ -1,7 +1,8 @@
+use log::info;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{Error, Result};
+use crate::{event::Event, pointer, Error, Result};
 
 /// `Pairing` represents paired controllers.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -36,7 +37,12 @@ impl Pairing {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+
     use super::*;
+    use crate::{config, event::EventEmitter, storage::MemoryStorage, Config};
 
     #[test]
     fn test_pairing_from_bytes() {
@@ -69,6 +75,60 @@ mod tests {
             b"{\"id\":\"bc158b86-cabf-432d-aee4-422ef0e3f1d5\",\"permissions\":\"0x00\",\"public_key\":[215,90,152,1,130,177,10,183,213,75,254,211,201,100,7,58,14,225,114,243,218,166,35,37,175,2,26,104,247,7,81,26]}".to_vec()
         );
     }
+
+    #[tokio::test]
+    async fn test_remove_last_admin() {
+        let config: pointer::Config = Arc::new(Mutex::new(Config::default()));
+        let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(MemoryStorage::new())));
+        let event_emitter: pointer::EventEmitter = Arc::new(Mutex::new(EventEmitter::new()));
+
+        let admin = Uuid::new_v4();
+        let admin_key = config::generate_ed25519_keypair().public.to_bytes();
+        let user = Uuid::new_v4();
+        let user_key = config::generate_ed25519_keypair().public.to_bytes();
+        add_pairing(&config, &storage, &event_emitter, admin, &admin_key, Permissions::Admin)
+            .await
+            .unwrap();
+        add_pairing(&config, &storage, &event_emitter, user, &user_key, Permissions::User)
+            .await
+            .unwrap();
+
+        assert!(
+            add_pairing(&config, &storage, &event_emitter, admin, &admin_key, Permissions::User)
+                .await
+                .is_err()
+        );
+
+        remove_pairing(&storage, &event_emitter, &admin).await.unwrap();
+        assert_eq!(storage.lock().await.count_pairings().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_add_invalid_public_key() {
+        let config: pointer::Config = Arc::new(Mutex::new(Config::default()));
+        let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(MemoryStorage::new())));
+        let event_emitter: pointer::EventEmitter = Arc::new(Mutex::new(EventEmitter::new()));
+
+        // the y-coordinate 2 isn't on the curve
+        let mut invalid_key = [0; 32];
+        invalid_key[0] = 2;
+        for key in &[&invalid_key[..], &[1; 31][..]] {
+            match add_pairing(
+                &config,
+                &storage,
+                &event_emitter,
+                Uuid::new_v4(),
+                key,
+                Permissions::Admin,
+            )
+            .await
+            {
+                Err(Error::InvalidPublicKey) => {},
+                _ => panic!("expected an invalid public key error"),
+            }
+        }
+        assert_eq!(storage.lock().await.count_pairings().await.unwrap(), 0);
+    }
 }
 
 /// The permissions of a paired controller.
@@ -98,3 +158,89 @@ impl Permissions {
         }
     }
 }
+
+/// Adds a pairing, or updates the permissions of an existing pairing with the same public key, and emits
+/// `Event::ControllerPaired`.
+pub(crate) async fn add_pairing(
+    config: &pointer::Config,
+    storage: &pointer::Storage,
+    event_emitter: &pointer::EventEmitter,
+    id: Uuid,
+    public_key: &[u8],
+    permissions: Permissions,
+) -> Result<()> {
+    // the key is used to verify the controller's signatures later on
+    let public_key = ed25519_dalek::PublicKey::from_bytes(public_key).map_err(|_| Error::InvalidPublicKey)?;
+
+    let mut s = storage.lock().await;
+    let pairing = match s.load_pairing(&id).await {
+        Ok(mut pairing) => {
+            if pairing.public_key != public_key.to_bytes() {
+                return Err(Error::InvalidPublicKey);
+            }
+            // demoting the last admin would leave nobody able to manage the pairings
+            if pairing.permissions == Permissions::Admin && permissions == Permissions::User {
+                let admin_count = s
+                    .list_pairings()
+                    .await?
+                    .iter()
+                    .filter(|p| p.permissions == Permissions::Admin)
+                    .count();
+                if admin_count == 1 {
+                    return Err(Error::LastAdmin);
+                }
+            }
+            pairing.permissions = permissions;
+            pairing
+        },
+        Err(_) => {
+            if let Some(max_peers) = config.lock().await.max_peers {
+                if s.count_pairings().await? + 1 > max_peers {
+                    return Err(Error::MaxPeers);
+                }
+            }
+
+            Pairing::new(id, permissions, public_key.to_bytes())
+        },
+    };
+    s.save_pairing(&pairing).await?;
+
+    drop(s);
+
+    event_emitter
+        .lock()
+        .await
+        .emit(&Event::ControllerPaired { id: pairing.id });
+
+    Ok(())
+}
+
+/// Removes a pairing and emits `Event::ControllerUnpaired`. If the last admin pairing is removed, all remaining
+/// pairings are removed as well, returning the accessory to the unpaired state.
+pub(crate) async fn remove_pairing(
+    storage: &pointer::Storage,
+    event_emitter: &pointer::EventEmitter,
+    id: &Uuid,
+) -> Result<()> {
+    let mut s = storage.lock().await;
+    s.delete_pairing(id).await?;
+    let mut removed = vec![*id];
+
+    let remaining = s.list_pairings().await?;
+    if !remaining.is_empty() && !remaining.iter().any(|p| p.permissions == Permissions::Admin) {
+        info!("last admin pairing removed; removing all remaining pairings");
+
+        for pairing in remaining {
+            s.delete_pairing(&pairing.id).await?;
+            removed.push(pairing.id);
+        }
+    }
+
+    drop(s);
+
+    for id in removed {
+        event_emitter.lock().await.emit(&Event::ControllerUnpaired { id });
+    }
+
+    Ok(())
+}
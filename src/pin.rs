@@ -1,4 +1,10 @@
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use srp::{
+    client::{srp_private_key, SrpClient},
+    groups::G_3072,
+};
 
 use crate::{Error, Result};
 
@@ -50,6 +56,9 @@ impl Pin {
         )
     }
 
+    /// Computes a `SetupVerifier` for the `Pin` with a random salt.
+    pub fn setup_verifier(&self) -> SetupVerifier { SetupVerifier::new(self) }
+
     // TODO: fix UTF-8 encoding here
     // pub fn as_bytes(&self) -> [u8; 10] {
     //     [
@@ -67,6 +76,36 @@ impl Pin {
     // }
 }
 
+/// SRP salt and password verifier derived from a setup `Pin`. Pair Setup can be run from a `SetupVerifier` alone, so
+/// the plaintext `Pin` doesn't have to be kept on the accessory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetupVerifier {
+    /// SRP salt `s`.
+    pub salt: [u8; 16],
+    /// SRP password verifier `v`.
+    pub verifier: Vec<u8>,
+}
+
+impl SetupVerifier {
+    /// Computes a `SetupVerifier` for a `Pin` with a random salt.
+    pub fn new(pin: &Pin) -> Self {
+        let mut csprng = OsRng {};
+        let mut salt = [0; 16]; // s
+        csprng.fill_bytes(&mut salt);
+
+        Self::with_salt(pin, salt)
+    }
+
+    /// Computes a `SetupVerifier` for a `Pin` with the given salt.
+    pub fn with_salt(pin: &Pin, salt: [u8; 16]) -> Self {
+        let private_key = srp_private_key::<Sha512>(b"Pair-Setup", pin.to_string().as_bytes(), &salt); // x = H(s | H(I | ":" | P))
+        let srp_client = SrpClient::<Sha512>::new(&private_key, &G_3072);
+        let verifier = srp_client.get_password_verifier(&private_key); // v = g^x
+
+        SetupVerifier { salt, verifier }
+    }
+}
+
 mod tests {
     #[allow(unused_imports)]
     use super::*;
@@ -83,6 +122,19 @@ mod tests {
         assert_eq!(pin.to_string(), "111-22-333".to_string());
     }
 
+    #[test]
+    fn test_setup_verifier() {
+        let pin = Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap();
+        let setup_verifier = pin.setup_verifier();
+        assert_eq!(SetupVerifier::with_salt(&pin, setup_verifier.salt), setup_verifier);
+
+        let other_pin = Pin::new([1, 1, 1, 2, 2, 3, 3, 4]).unwrap();
+        assert_ne!(
+            SetupVerifier::with_salt(&other_pin, setup_verifier.salt),
+            setup_verifier
+        );
+    }
+
     // #[test]
     // fn test_as_bytes() {
     //     let pin = Pin::new([1, 1, 1, 2, 2, 3, 3, 3]).unwrap();
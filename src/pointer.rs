@@ -3,7 +3,7 @@ use std::sync::{Arc, RwLock};
 use futures::lock::Mutex;
 use uuid::Uuid;
 
-use crate::{accessory, event, storage};
+use crate::{accessory, event, session, storage};
 
 pub type ControllerId = Arc<RwLock<Option<Uuid>>>;
 
@@ -11,9 +11,11 @@ pub type EventEmitter = Arc<Mutex<event::EventEmitter>>;
 
 pub type EventSubscriptions = Arc<Mutex<Vec<(u64, u64)>>>;
 
+pub type SessionRegistry = Arc<Mutex<session::SessionRegistry>>;
+
 pub type AccessoryList = Arc<Mutex<storage::accessory_list::AccessoryList>>;
 
-pub type Accessory = Arc<Mutex<Box<dyn accessory::HapAccessory>>>;
+pub type Accessory = Arc<Mutex<dyn accessory::HapAccessory>>;
 
 pub type Storage = Arc<Mutex<Box<dyn storage::Storage>>>;
 
@@ -1,21 +1,34 @@
-use std::sync::Arc;
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use async_trait::async_trait;
 use futures::{
     future::{self, BoxFuture, FutureExt},
     lock::Mutex,
+    stream::{Stream, StreamExt},
 };
-use log::info;
+use log::{error, info};
+use uuid::Uuid;
 
 use crate::{
-    accessory::HapAccessory,
-    config::Config,
-    event::{Event, EventEmitter},
+    accessory::{AccessoryHandle, HapAccessory},
+    characteristic::{CharacteristicChange, Perm},
+    config::{self, Config},
+    event::{Event, EventEmitter, EventStream},
+    pairing::{self, Pairing, Permissions},
     pointer,
     server::Server,
+    session::{SessionId, SessionInfo, SessionRegistry},
     storage::{accessory_list::AccessoryList, Storage},
     transport::{http::server::Server as HttpServer, mdns::MdnsResponder},
     BonjourStatusFlag,
+    Error,
     Result,
 };
 
@@ -26,8 +39,11 @@ pub struct IpServer {
     storage: pointer::Storage,
     accessory_list: pointer::AccessoryList,
     event_emitter: pointer::EventEmitter,
+    session_registry: pointer::SessionRegistry,
     http_server: HttpServer,
     mdns_responder: MdnsResponder,
+    persisted_characteristics: Arc<Mutex<HashSet<(u64, u64)>>>,
+    running: Arc<AtomicBool>,
 }
 
 impl IpServer {
@@ -77,8 +93,15 @@ impl IpServer {
         let config = Arc::new(Mutex::new(config));
         let storage: pointer::Storage = Arc::new(Mutex::new(Box::new(storage)));
 
+        let session_registry = Arc::new(Mutex::new(SessionRegistry::new()));
+        let mdns_responder = MdnsResponder::new(config.clone());
+        let persisted_characteristics = Arc::new(Mutex::new(HashSet::new()));
+
         let config_ = config.clone();
         let storage_ = storage.clone();
+        let session_registry_ = session_registry.clone();
+        let mdns_responder_ = mdns_responder.clone();
+        let persisted_characteristics_ = persisted_characteristics.clone();
         let mut event_emitter = EventEmitter::new();
 
         // TODO: count pairings & override `config.status_flag`
@@ -86,30 +109,32 @@ impl IpServer {
         event_emitter.add_listener(Box::new(move |event| {
             let config_ = config_.clone();
             let storage_ = storage_.clone();
+            let session_registry_ = session_registry_.clone();
+            let mdns_responder_ = mdns_responder_.clone();
+            let persisted_characteristics_ = persisted_characteristics_.clone();
             async move {
                 match *event {
                     Event::ControllerPaired { id } => {
                         info!("controller {} paired", id);
 
-                        if let Ok(count) = storage_.lock().await.count_pairings().await {
-                            if count > 0 {
-                                info!("1 or more controllers paired; setting Bonjour status flag to `Zero`");
-
-                                let mut c = config_.lock().await;
-                                c.status_flag = BonjourStatusFlag::Zero;
-                            }
-                        }
+                        update_status_flag(&config_, &storage_, &mdns_responder_).await;
                     },
                     Event::ControllerUnpaired { id } => {
                         info!("controller {} unpaired", id);
 
-                        if let Ok(count) = storage_.lock().await.count_pairings().await {
-                            if count == 0 {
-                                info!("0 controllers paired; setting Bonjour status flag to `Not Paired`");
+                        session_registry_.lock().await.disconnect_controller(&id);
+                        update_status_flag(&config_, &storage_, &mdns_responder_).await;
+                    },
+                    Event::CharacteristicValueChanged {
+                        aid, iid, ref value, ..
+                    } => {
+                        let persisted = persisted_characteristics_.lock().await.contains(&(aid, iid));
+                        if !persisted {
+                            return;
+                        }
 
-                                let mut c = config_.lock().await;
-                                c.status_flag = BonjourStatusFlag::NotPaired;
-                            }
+                        if let Err(e) = storage_.lock().await.save_characteristic_value(aid, iid, value).await {
+                            error!("couldn't persist the value of characteristic {}.{}: {:?}", aid, iid, e);
                         }
                     },
                     _ => {},
@@ -126,50 +151,448 @@ impl IpServer {
             storage.clone(),
             accessory_list.clone(),
             event_emitter.clone(),
+            session_registry.clone(),
         );
-        let mdns_responder = MdnsResponder::new(config.clone());
 
         let server = IpServer {
             config,
             storage,
             accessory_list,
             event_emitter,
+            session_registry,
             http_server,
             mdns_responder,
+            persisted_characteristics,
+            running: Arc::new(AtomicBool::new(false)),
         };
 
         Ok(server)
     }
+
+    /// Returns a stream yielding every `Event` emitted by the server. Events are queued on the stream, so a slow
+    /// consumer doesn't hold up request handling. Dropping the stream ends the subscription.
+    pub async fn subscribe(&self) -> EventStream { self.event_emitter.lock().await.subscribe() }
+
+    /// Returns the pairings of the server.
+    pub async fn list_pairings(&self) -> Result<Vec<Pairing>> { self.storage.lock().await.list_pairings().await }
+
+    /// Removes the pairing of the controller with the given ID, the same way a controller removing it would. Removing
+    /// the last admin pairing removes all pairings.
+    pub async fn remove_pairing(&self, id: &Uuid) -> Result<()> {
+        pairing::remove_pairing(&self.storage, &self.event_emitter, id).await
+    }
+
+    /// Sets the permissions of the pairing of the controller with the given ID, e.g. to promote a user to an admin.
+    pub async fn set_pairing_permissions(&self, id: &Uuid, permissions: Permissions) -> Result<()> {
+        let pairing = self.storage.lock().await.load_pairing(id).await?;
+        pairing::add_pairing(
+            &self.config,
+            &self.storage,
+            &self.event_emitter,
+            pairing.id,
+            &pairing.public_key,
+            permissions,
+        )
+        .await
+    }
+
+    /// Resets the server to the state of a brand-new accessory. All pairings and persisted Characteristic values are
+    /// removed, a new device ID and Ed25519 keypair are generated, the configuration number is reset and the new
+    /// `Config` is persisted. All sessions are closed and the server re-announces itself as unpaired.
+    ///
+    /// The Characteristics of the added Accessories keep their current values, but aren't persisted anymore.
+    /// Application data saved through [`Storage::save_bytes`](crate::storage::Storage::save_bytes) is left untouched.
+    ///
+    /// The server doesn't keep identifier maps of its own, the AIDs and IIDs are the ones the application assigns to
+    /// its Accessories, so there are none to reset. A fresh configuration hash is taken from the current Accessories.
+    pub async fn factory_reset(&self) -> Result<()> {
+        info!("factory resetting the accessory server");
+
+        // removing the last admin pairing removes all remaining pairings along with it
+        while let Some(pairing) = self.list_pairings().await?.pop() {
+            self.remove_pairing(&pairing.id).await?;
+        }
+
+        let hash = self.accessory_list.lock().await.structure_hash().await?;
+
+        let mut s = self.storage.lock().await;
+        s.delete_characteristic_values().await?;
+        self.persisted_characteristics.lock().await.clear();
+
+        let mut c = self.config.lock().await;
+
+        let configuration_number_changed = c.configuration_number != 1;
+        c.device_id = config::generate_random_mac_address();
+        c.device_ed25519_keypair = config::generate_ed25519_keypair();
+        c.configuration_number = 1;
+        c.configuration_hash = Some(hash);
+        c.status_flag = BonjourStatusFlag::NotPaired;
+
+        s.save_config(&c).await?;
+
+        drop(c);
+        drop(s);
+
+        self.session_registry.lock().await.disconnect_all();
+        self.mdns_responder.update_records();
+
+        if configuration_number_changed {
+            self.event_emitter
+                .lock()
+                .await
+                .emit(&Event::ConfigurationNumberChanged {
+                    configuration_number: 1,
+                });
+        }
+
+        Ok(())
+    }
+
+    /// Returns a snapshot of the active sessions of the server.
+    pub async fn sessions(&self) -> Vec<SessionInfo> {
+        let mut sessions = self.session_registry.lock().await.list().await;
+
+        let storage = self.storage.lock().await;
+        for session in &mut sessions {
+            if let Some(ref controller_id) = session.controller_id {
+                // the pairing may have been removed while the session is being torn down
+                session.permissions = storage.load_pairing(controller_id).await.ok().map(|p| p.permissions);
+            }
+        }
+
+        sessions
+    }
+
+    /// Disconnects the session with the given ID. A request that is currently being handled on the session is answered
+    /// before the connection is closed.
+    pub async fn disconnect_session(&self, id: SessionId) -> Result<()> {
+        self.session_registry.lock().await.disconnect(id)
+    }
+
+    /// Returns a stream yielding every change to the value of a Characteristic on any Accessory of the server,
+    /// regardless of whether it was made by a controller or by the application. Dropping the stream stops watching.
+    pub async fn watch(&self) -> impl Stream<Item = CharacteristicChange> {
+        self.subscribe().await.filter_map(|event| {
+            future::ready(match event {
+                Event::CharacteristicValueChanged {
+                    aid,
+                    iid,
+                    value,
+                    origin,
+                } => Some(CharacteristicChange {
+                    aid,
+                    iid,
+                    value,
+                    origin,
+                }),
+                _ => None,
+            })
+        })
+    }
 }
 
 #[async_trait]
 impl Server for IpServer {
     fn run_handle(&self) -> BoxFuture<()> {
+        let config = self.config.clone();
+        let storage = self.storage.clone();
+        let accessory_list = self.accessory_list.clone();
+        let event_emitter = self.event_emitter.clone();
+        let running = self.running.clone();
+
         let http_handle = self.http_server.run_handle();
         let mdns_handle = self.mdns_responder.run_handle();
 
-        Box::pin(future::join(http_handle, mdns_handle).map(|_| ()).boxed())
+        async move {
+            if let Err(e) = update_configuration_number(&config, &storage, &accessory_list, &event_emitter).await {
+                error!("couldn't update the configuration number: {:?}", e);
+            }
+            running.store(true, Ordering::SeqCst);
+
+            future::join(http_handle, mdns_handle).map(|_| ()).await
+        }
+        .boxed()
     }
 
     fn config_pointer(&self) -> pointer::Config { self.config.clone() }
 
     fn storage_pointer(&self) -> pointer::Storage { self.storage.clone() }
 
-    async fn add_accessory<A: HapAccessory + 'static>(&mut self, accessory: A) -> Result<pointer::Accessory> {
-        let accessory = self.accessory_list.lock().await.add_accessory(Box::new(accessory))?;
+    async fn add_accessory<A: HapAccessory + 'static>(&mut self, accessory: A) -> Result<AccessoryHandle<A>> {
+        let accessory = AccessoryHandle::new(accessory);
+
+        // the values are restored before the Accessory is published, so a controller can't write a value that's then
+        // overwritten by the persisted one
+        let persisted = restore_characteristic_values(&accessory.pointer(), &self.config, &self.storage).await;
+        let mut added = Vec::new();
+        {
+            let mut p = self.persisted_characteristics.lock().await;
+            for id in persisted {
+                if p.insert(id) {
+                    added.push(id);
+                }
+            }
+        }
+
+        if let Err(e) = self
+            .accessory_list
+            .lock()
+            .await
+            .add_accessory(accessory.pointer())
+            .await
+        {
+            let mut p = self.persisted_characteristics.lock().await;
+            for id in &added {
+                p.remove(id);
+            }
+            return Err(e);
+        }
 
-        let mut config = self.config.lock().await;
-        config.configuration_number += 1;
+        if self.running.load(Ordering::SeqCst)
+            && update_configuration_number(&self.config, &self.storage, &self.accessory_list, &self.event_emitter)
+                .await?
+        {
+            self.mdns_responder.update_records();
+        }
 
         Ok(accessory)
     }
 
-    async fn remove_accessory(&mut self, accessory: &pointer::Accessory) -> Result<()> {
-        self.accessory_list.lock().await.remove_accessory(&accessory).await?;
+    async fn remove_accessory<A: HapAccessory + 'static>(&mut self, accessory: &AccessoryHandle<A>) -> Result<()> {
+        self.accessory_list
+            .lock()
+            .await
+            .remove_accessory(&accessory.pointer())
+            .await?;
+        let aid = accessory.pointer().lock().await.get_id();
+        self.persisted_characteristics.lock().await.retain(|&(a, _)| a != aid);
 
-        let mut config = self.config.lock().await;
-        config.configuration_number += 1;
+        if self.running.load(Ordering::SeqCst)
+            && update_configuration_number(&self.config, &self.storage, &self.accessory_list, &self.event_emitter)
+                .await?
+        {
+            self.mdns_responder.update_records();
+        }
 
         Ok(())
     }
 }
+
+/// Restores the persisted values of the Characteristics of an Accessory that are marked as persistent, or that are
+/// writable if `persist_writable_values` is set on the `Config`, without calling any callbacks. Returns the IDs of the
+/// persisted Characteristics.
+async fn restore_characteristic_values(
+    accessory: &pointer::Accessory,
+    config: &pointer::Config,
+    storage: &pointer::Storage,
+) -> Vec<(u64, u64)> {
+    let persist_writable_values = config.lock().await.persist_writable_values;
+
+    let mut a = accessory.lock().await;
+    let s = storage.lock().await;
+    let aid = a.get_id();
+    let mut persisted = Vec::new();
+    for service in a.get_mut_services() {
+        for characteristic in service.get_mut_characteristics() {
+            let writable = characteristic.get_perms().contains(&Perm::PairedWrite);
+            if !(characteristic.is_persistent() || persist_writable_values && writable) {
+                continue;
+            }
+
+            let iid = characteristic.get_id();
+            persisted.push((aid, iid));
+
+            match s.load_characteristic_value(aid, iid).await {
+                Ok(value) =>
+                    if let Err(e) = characteristic.restore_value(value) {
+                        error!("couldn't restore the value of characteristic {}.{}: {:?}", aid, iid, e);
+                    },
+                Err(Error::Io(ref e)) if e.kind() == ErrorKind::NotFound => {},
+                Err(e) => error!("couldn't load the value of characteristic {}.{}: {:?}", aid, iid, e),
+            }
+        }
+    }
+
+    persisted
+}
+
+/// Sets the Bonjour status flag according to whether there are any pairings left and triggers a re-announcement of the
+/// TXT records if it changed.
+async fn update_status_flag(config: &pointer::Config, storage: &pointer::Storage, mdns_responder: &MdnsResponder) {
+    let paired = match storage.lock().await.list_pairings().await {
+        Ok(pairings) => !pairings.is_empty(),
+        Err(e) => {
+            error!("couldn't list pairings: {:?}", e);
+            return;
+        },
+    };
+
+    let mut c = config.lock().await;
+    let not_paired = matches!(c.status_flag, BonjourStatusFlag::NotPaired);
+    if paired && not_paired {
+        info!("1 or more controllers paired; setting Bonjour status flag to `Zero`");
+        c.status_flag = BonjourStatusFlag::Zero;
+    } else if !paired && !not_paired {
+        info!("0 controllers paired; setting Bonjour status flag to `Not Paired`");
+        c.status_flag = BonjourStatusFlag::NotPaired;
+    } else {
+        return;
+    }
+    drop(c);
+
+    mdns_responder.update_records();
+}
+
+/// Hashes the structure of the accessory database and increments the configuration number if the hash differs from the
+/// one the current configuration number was issued for. The configuration number and the hash are persisted afterwards.
+/// Returns whether the configuration number was changed.
+///
+/// Accessories added or removed before the server is started only lead to a single check when it's started, so the
+/// configuration number isn't incremented on every restart.
+async fn update_configuration_number(
+    config: &pointer::Config,
+    storage: &pointer::Storage,
+    accessory_list: &pointer::AccessoryList,
+    event_emitter: &pointer::EventEmitter,
+) -> Result<bool> {
+    let hash = accessory_list.lock().await.structure_hash().await?;
+
+    let mut s = storage.lock().await;
+    let mut c = config.lock().await;
+
+    if c.configuration_hash == Some(hash) {
+        return Ok(false);
+    }
+
+    let changed = c.configuration_hash.is_some();
+    if changed {
+        // the configuration number has to wrap around to 1 after overflowing 65535
+        c.configuration_number = if c.configuration_number >= 65535 {
+            1
+        } else {
+            c.configuration_number + 1
+        };
+
+        info!(
+            "accessory database changed; configuration number is now {}",
+            c.configuration_number
+        );
+    }
+    c.configuration_hash = Some(hash);
+
+    s.save_config(&c).await?;
+
+    if changed {
+        let configuration_number = c.configuration_number;
+        drop(c);
+        drop(s);
+
+        event_emitter
+            .lock()
+            .await
+            .emit(&Event::ConfigurationNumberChanged { configuration_number });
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+        characteristic::{CharacteristicCallbacks, HapCharacteristic},
+        storage::MemoryStorage,
+    };
+
+    #[tokio::test]
+    async fn test_persisted_characteristic_values() {
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        lightbulb.lightbulb.on.set_persistent(true);
+        lightbulb.lightbulb.on.on_update(Some(|_: &bool, _: &bool| {
+            panic!("restoring a value mustn't call on_update")
+        }));
+        let iid = lightbulb.lightbulb.on.get_id();
+
+        let mut storage = MemoryStorage::new();
+        storage.save_characteristic_value(1, iid, &json!(true)).await.unwrap();
+
+        let mut server = IpServer::new(Config::default(), storage).unwrap();
+        let lightbulb = server.add_accessory(lightbulb).await.unwrap();
+        assert_eq!(lightbulb.lock().await.lightbulb.on.get_value().await.unwrap(), true);
+
+        let mut l = lightbulb.lock().await;
+        l.lightbulb.on.on_update(None::<fn(&bool, &bool)>);
+        l.lightbulb.on.set_value(false).await.unwrap();
+        drop(l);
+
+        // the value is persisted by a listener handling the emitted event in the background
+        loop {
+            let value = server
+                .storage
+                .lock()
+                .await
+                .load_characteristic_value(1, iid)
+                .await
+                .unwrap();
+            if value == json!(false) {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_accessory_values() {
+        let mut server = IpServer::new(Config::default(), MemoryStorage::new()).unwrap();
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        lightbulb.lightbulb.on.set_persistent(true);
+        let lightbulb = server.add_accessory(lightbulb).await.unwrap();
+
+        let mut duplicate = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        duplicate.lightbulb.on.set_persistent(true);
+        let iid = duplicate.lightbulb.on.get_id();
+        server
+            .storage
+            .lock()
+            .await
+            .save_characteristic_value(1, iid, &json!(true))
+            .await
+            .unwrap();
+
+        assert!(server.add_accessory(duplicate).await.is_err());
+        // the Characteristic of the already added Accessory stays persisted
+        assert_eq!(
+            server.persisted_characteristics.lock().await.iter().collect::<Vec<_>>(),
+            vec![&(1, iid)]
+        );
+        assert_eq!(lightbulb.lock().await.lightbulb.on.get_value().await.unwrap(), false);
+    }
+
+    #[tokio::test]
+    async fn test_factory_reset_storage() {
+        let mut lightbulb = LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap();
+        lightbulb.lightbulb.on.set_persistent(true);
+        let iid = lightbulb.lightbulb.on.get_id();
+
+        let mut storage = MemoryStorage::new();
+        storage.save_characteristic_value(1, iid, &json!(true)).await.unwrap();
+        storage.save_bytes("app", "state", b"on").await.unwrap();
+
+        let mut server = IpServer::new(Config::default(), storage).unwrap();
+        let device_id = server.config.lock().await.device_id;
+        server.add_accessory(lightbulb).await.unwrap();
+
+        server.factory_reset().await.unwrap();
+
+        let s = server.storage.lock().await;
+        assert!(s.load_characteristic_value(1, iid).await.is_err());
+        assert_eq!(s.load_bytes("app", "state").await.unwrap(), b"on".to_vec());
+        assert!(s.list_pairings().await.unwrap().is_empty());
+        assert_ne!(s.load_config().await.unwrap().device_id, device_id);
+        drop(s);
+        assert!(server.persisted_characteristics.lock().await.is_empty());
+    }
+}
@@ -1,7 +1,11 @@
 use async_trait::async_trait;
 use futures::future::BoxFuture;
 
-use crate::{accessory::HapAccessory, pointer, Result};
+use crate::{
+    accessory::{AccessoryHandle, HapAccessory},
+    pointer,
+    Result,
+};
 
 mod ip;
 
@@ -16,8 +20,8 @@ pub trait Server {
     fn config_pointer(&self) -> pointer::Config;
     /// Returns a pointer to the `Storage` of the server.
     fn storage_pointer(&self) -> pointer::Storage;
-    /// Adds an Accessory to the server and returns a pointer to the added Accessory.
-    async fn add_accessory<A: HapAccessory + 'static>(&mut self, accessory: A) -> Result<pointer::Accessory>;
-    /// Takes a pointer to an Accessory by reference and removes the Accessory from the server.
-    async fn remove_accessory(&mut self, accessory: &pointer::Accessory) -> Result<()>;
+    /// Adds an Accessory to the server and returns a typed handle to the added Accessory.
+    async fn add_accessory<A: HapAccessory + 'static>(&mut self, accessory: A) -> Result<AccessoryHandle<A>>;
+    /// Takes a handle to an Accessory by reference and removes the Accessory from the server.
+    async fn remove_accessory<A: HapAccessory + 'static>(&mut self, accessory: &AccessoryHandle<A>) -> Result<()>;
 }
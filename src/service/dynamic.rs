@@ -0,0 +1,245 @@
+use std::fmt;
+
+use serde::{
+    ser::{Serialize, SerializeStruct, Serializer},
+    Deserialize,
+};
+
+use crate::{
+    characteristic::{new_characteristic, CharacteristicOverrides, HapCharacteristic},
+    service::{service_characteristic_types, HapService},
+    Error,
+    HapType,
+    Result,
+};
+
+/// Declarative description of a Service, e.g. read from a configuration file.
+///
+/// Service and Characteristic types are given by name, e.g. `"Lightbulb"`, or by UUID, e.g. `"43"`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::service::ServiceDescription;
+///
+/// let description: ServiceDescription = serde_json::from_str(
+///     r#"{
+///         "type": "Lightbulb",
+///         "primary": true,
+///         "characteristics": [
+///             { "type": "Brightness", "value": 100, "max_value": 80 }
+///         ]
+///     }"#,
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceDescription {
+    /// Name or UUID of the Service type.
+    #[serde(rename = "type")]
+    pub service_type: String,
+    /// Specifies if the Service is the primary Service of the Accessory.
+    #[serde(default)]
+    pub primary: bool,
+    /// Specifies if the Service is hidden.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Optional Characteristics to enable, and initial values and metadata overrides for required ones.
+    #[serde(default)]
+    pub characteristics: Vec<CharacteristicDescription>,
+    /// Positions of the Services linked to the Service in the Service list of the `AccessoryDescription`.
+    #[serde(default)]
+    pub linked: Vec<usize>,
+}
+
+/// Declarative description of a Characteristic of a `ServiceDescription`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CharacteristicDescription {
+    /// Name or UUID of the Characteristic type.
+    #[serde(rename = "type")]
+    pub characteristic_type: String,
+    /// Initial value of the Characteristic.
+    pub value: Option<serde_json::Value>,
+    /// Metadata overrides of the Characteristic.
+    #[serde(flatten)]
+    pub overrides: CharacteristicOverrides,
+    /// Specifies if the value of the Characteristic is persisted across restarts.
+    #[serde(default)]
+    pub persistent: bool,
+}
+
+/// A Service constructed at runtime, e.g. from a `ServiceDescription`, instead of being defined at compile time.
+#[derive(Default)]
+pub struct DynamicService {
+    /// ID of the Service.
+    id: u64,
+    /// ID of the Accessory the Service belongs to.
+    accessory_id: u64,
+    /// `HapType` of the Service.
+    hap_type: HapType,
+    /// Specifies if the Service is hidden.
+    hidden: bool,
+    /// Specifies if the Service is the primary Service of the Accessory.
+    primary: bool,
+    /// IDs of the Services linked to the Service.
+    linked_services: Vec<u64>,
+
+    /// The required Characteristics of the Service, followed by the enabled optional ones.
+    characteristics: Vec<Box<dyn HapCharacteristic>>,
+}
+
+impl DynamicService {
+    /// Creates a new Service of the given type with all of its required Characteristics. The Characteristics are
+    /// given consecutive IDs following the ID of the Service.
+    pub fn new(id: u64, accessory_id: u64, hap_type: HapType) -> Result<Self> {
+        let (required, _) =
+            service_characteristic_types(hap_type).ok_or(Error::UnknownHapType(format!("{:?}", hap_type)))?;
+
+        let mut service = DynamicService {
+            id,
+            accessory_id,
+            hap_type,
+            ..Default::default()
+        };
+        for &characteristic_type in required {
+            service.push_characteristic(characteristic_type)?;
+        }
+
+        Ok(service)
+    }
+
+    /// Creates a new Service from a description.
+    pub fn from_description(id: u64, accessory_id: u64, description: &ServiceDescription) -> Result<Self> {
+        let hap_type = parse_hap_type(&description.service_type)?;
+        let mut service = Self::new(id, accessory_id, hap_type)?;
+        service.set_primary(description.primary);
+        service.set_hidden(description.hidden);
+
+        for characteristic_description in &description.characteristics {
+            let characteristic_type = parse_hap_type(&characteristic_description.characteristic_type)?;
+            if service.get_characteristic(characteristic_type).is_none() {
+                service.add_characteristic(characteristic_type)?;
+            }
+            let characteristic = service
+                .get_mut_characteristic(characteristic_type)
+                .expect("the characteristic was just added");
+
+            characteristic_description.overrides.apply(characteristic)?;
+            if let Some(ref value) = characteristic_description.value {
+                characteristic.restore_value(value.clone())?;
+            }
+            characteristic.set_persistent(characteristic_description.persistent);
+        }
+
+        Ok(service)
+    }
+
+    /// Enables an optional Characteristic of the Service and returns a mutable reference to it. The Characteristic is
+    /// given the next ID following the ones of the existing Characteristics, which may already belong to the next
+    /// Service of the Accessory. Use
+    /// [`DynamicAccessory::add_characteristic`](crate::accessory::DynamicAccessory::add_characteristic)
+    /// for a Service of a `DynamicAccessory`, or reassign the IDs of the Accessory afterwards.
+    pub fn add_characteristic(&mut self, hap_type: HapType) -> Result<&mut dyn HapCharacteristic> {
+        let (_, optional) =
+            service_characteristic_types(self.hap_type).ok_or(Error::UnknownHapType(format!("{:?}", self.hap_type)))?;
+        if !optional.contains(&hap_type) || self.get_characteristic(hap_type).is_some() {
+            return Err(Error::UnsupportedCharacteristic(self.hap_type, hap_type));
+        }
+
+        self.push_characteristic(hap_type)
+    }
+
+    fn push_characteristic(&mut self, hap_type: HapType) -> Result<&mut dyn HapCharacteristic> {
+        let id = self.id + 1 + self.characteristics.len() as u64;
+        let characteristic = new_characteristic(hap_type, id, self.accessory_id)
+            .ok_or(Error::UnknownHapType(format!("{:?}", hap_type)))?;
+        self.characteristics.push(characteristic);
+
+        Ok(self.characteristics.last_mut().unwrap().as_mut())
+    }
+}
+
+/// Looks up a `HapType` by name or UUID.
+fn parse_hap_type(name_or_uuid: &str) -> Result<HapType> {
+    HapType::from_name_or_uuid(name_or_uuid).ok_or_else(|| Error::UnknownHapType(name_or_uuid.to_string()))
+}
+
+impl fmt::Debug for DynamicService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynamicService")
+            .field("id", &self.id)
+            .field("accessory_id", &self.accessory_id)
+            .field("hap_type", &self.hap_type)
+            .field("hidden", &self.hidden)
+            .field("primary", &self.primary)
+            .field(
+                "characteristics",
+                &self.characteristics.iter().map(|c| c.get_type()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl HapService for DynamicService {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_type(&self) -> HapType { self.hap_type }
+
+    fn get_hidden(&self) -> bool { self.hidden }
+
+    fn set_hidden(&mut self, hidden: bool) { self.hidden = hidden; }
+
+    fn get_primary(&self) -> bool { self.primary }
+
+    fn set_primary(&mut self, primary: bool) { self.primary = primary; }
+
+    fn get_linked_services(&self) -> Vec<u64> { self.linked_services.clone() }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) { self.linked_services = linked_services; }
+
+    fn get_characteristic(&self, hap_type: HapType) -> Option<&dyn HapCharacteristic> {
+        for characteristic in self.get_characteristics() {
+            if characteristic.get_type() == hap_type {
+                return Some(characteristic);
+            }
+        }
+        None
+    }
+
+    fn get_mut_characteristic(&mut self, hap_type: HapType) -> Option<&mut dyn HapCharacteristic> {
+        for characteristic in self.get_mut_characteristics() {
+            if characteristic.get_type() == hap_type {
+                return Some(characteristic);
+            }
+        }
+        None
+    }
+
+    fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic> {
+        self.characteristics.iter().map(|c| c.as_ref()).collect()
+    }
+
+    fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic> {
+        self.characteristics
+            .iter_mut()
+            .map(|c| c.as_mut() as &mut dyn HapCharacteristic)
+            .collect()
+    }
+}
+
+impl Serialize for DynamicService {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapService", 6)?;
+        state.serialize_field("iid", &self.get_id())?;
+        state.serialize_field("type", &self.get_type())?;
+        state.serialize_field("hidden", &self.get_hidden())?;
+        state.serialize_field("primary", &self.get_primary())?;
+        state.serialize_field("characteristics", &self.get_characteristics())?;
+        if !self.get_linked_services().is_empty() {
+            state.serialize_field("linked", &self.get_linked_services())?;
+        }
+        state.end()
+    }
+}
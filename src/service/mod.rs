@@ -2,14 +2,22 @@ use erased_serde::serialize_trait_object;
 
 use crate::{characteristic::HapCharacteristic, HapType};
 
+mod dynamic;
 mod generated;
 
-pub use crate::service::generated::*;
+pub use hap_derive::HapService;
+
+pub use crate::service::{
+    dynamic::{CharacteristicDescription, DynamicService, ServiceDescription},
+    generated::*,
+};
 
 /// `HapService` is implemented by the inner type of every `Service`.
 pub trait HapService: erased_serde::Serialize + Send + Sync {
     /// Returns the ID of a Service.
     fn get_id(&self) -> u64;
+    /// Sets the ID of a Service.
+    fn set_id(&mut self, id: u64);
     /// Returns the `HapType` of a Service.
     fn get_type(&self) -> HapType;
     /// Returns the hidden value of a Service.
@@ -20,6 +28,11 @@ pub trait HapService: erased_serde::Serialize + Send + Sync {
     fn get_primary(&self) -> bool;
     /// Sets the primary value of a Service.
     fn set_primary(&mut self, primary: bool);
+    /// Returns the IDs of the Services linked to a Service.
+    fn get_linked_services(&self) -> Vec<u64>;
+    /// Sets the IDs of the Services linked to a Service. Accessories deriving `HapAccessory` link their Services with
+    /// `#[service(linked(...))]` instead, which keeps the links in sync with the assigned IDs.
+    fn set_linked_services(&mut self, linked_services: Vec<u64>);
     /// Returns a reference to a specific Characteristic of the Service if it's present on it.
     fn get_characteristic(&self, hap_type: HapType) -> Option<&dyn HapCharacteristic>;
     /// Returns a mutable reference to a specific Characteristic of the Service if it's present on it.
@@ -28,6 +41,14 @@ pub trait HapService: erased_serde::Serialize + Send + Sync {
     fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic>;
     /// Returns mutable references to the Characteristics of a Service.
     fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic>;
+    /// Returns a reference to the Characteristic with the given instance ID if it's present on the Service.
+    fn get_characteristic_by_id(&self, id: u64) -> Option<&dyn HapCharacteristic> {
+        self.get_characteristics().into_iter().find(|c| c.get_id() == id)
+    }
+    /// Returns a mutable reference to the Characteristic with the given instance ID if it's present on the Service.
+    fn get_mut_characteristic_by_id(&mut self, id: u64) -> Option<&mut dyn HapCharacteristic> {
+        self.get_mut_characteristics().into_iter().find(|c| c.get_id() == id)
+    }
 }
 
 serialize_trait_object!(HapService);
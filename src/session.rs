@@ -0,0 +1,187 @@
+use std::{fmt, net::SocketAddr, time::SystemTime};
+
+use futures::channel::oneshot;
+use log::info;
+use uuid::Uuid;
+
+use crate::{pairing::Permissions, pointer, Error, Result};
+
+/// Maximum number of concurrent sessions a server accepts.
+pub const MAX_SESSIONS: usize = 16;
+
+/// ID of a session, unique for the lifetime of a server.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SessionId(u64);
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.0) }
+}
+
+/// A snapshot of an active session, i.e. a TCP connection of a controller to the server.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    /// ID of the session.
+    pub id: SessionId,
+    /// ID of the controller, if the session has been verified.
+    pub controller_id: Option<Uuid>,
+    /// Permissions of the controller's pairing, if the session has been verified.
+    pub permissions: Option<Permissions>,
+    /// Address of the controller.
+    pub peer_addr: SocketAddr,
+    /// Point in time the connection was accepted.
+    pub connected_since: SystemTime,
+    /// Characteristics the session is subscribed to, as `(aid, iid)` pairs.
+    pub subscriptions: Vec<(u64, u64)>,
+}
+
+struct Session {
+    id: SessionId,
+    peer_addr: SocketAddr,
+    connected_since: SystemTime,
+    controller_id: pointer::ControllerId,
+    event_subscriptions: pointer::EventSubscriptions,
+    shutdown_sender: Option<oneshot::Sender<()>>,
+}
+
+/// Keeps track of the active sessions of a server.
+#[derive(Default)]
+pub(crate) struct SessionRegistry {
+    sessions: Vec<Session>,
+    next_id: u64,
+}
+
+impl SessionRegistry {
+    pub fn new() -> SessionRegistry { SessionRegistry::default() }
+
+    /// Registers a new session. Returns the ID of the session and a receiver that is resolved when the session is
+    /// asked to disconnect.
+    pub fn register(
+        &mut self,
+        peer_addr: SocketAddr,
+        controller_id: pointer::ControllerId,
+        event_subscriptions: pointer::EventSubscriptions,
+    ) -> Result<(SessionId, oneshot::Receiver<()>)> {
+        if self.sessions.len() >= MAX_SESSIONS {
+            return Err(Error::MaxSessions);
+        }
+
+        let id = SessionId(self.next_id);
+        self.next_id += 1;
+
+        let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        self.sessions.push(Session {
+            id,
+            peer_addr,
+            connected_since: SystemTime::now(),
+            controller_id,
+            event_subscriptions,
+            shutdown_sender: Some(shutdown_sender),
+        });
+
+        Ok((id, shutdown_receiver))
+    }
+
+    /// Removes a closed session.
+    pub fn unregister(&mut self, id: SessionId) { self.sessions.retain(|s| s.id != id); }
+
+    /// Returns a snapshot of every active session, leaving the permissions to be filled in by the caller.
+    pub async fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions = Vec::new();
+        for session in &self.sessions {
+            let controller_id = *session.controller_id.read().expect("reading controller_id");
+            sessions.push(SessionInfo {
+                id: session.id,
+                controller_id,
+                permissions: None,
+                peer_addr: session.peer_addr,
+                connected_since: session.connected_since,
+                subscriptions: session.event_subscriptions.lock().await.clone(),
+            });
+        }
+        sessions
+    }
+
+    /// Asks the session with the given ID to disconnect.
+    pub fn disconnect(&mut self, id: SessionId) -> Result<()> {
+        let session = self
+            .sessions
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or(Error::SessionNotFound)?;
+        if let Some(sender) = session.shutdown_sender.take() {
+            info!("disconnecting session {} from {}", id, session.peer_addr);
+            let _ = sender.send(());
+        }
+
+        Ok(())
+    }
+
+    /// Asks every session to disconnect.
+    pub fn disconnect_all(&mut self) {
+        for session in &mut self.sessions {
+            if let Some(sender) = session.shutdown_sender.take() {
+                let _ = sender.send(());
+            }
+        }
+    }
+
+    /// Asks every session of the given controller to disconnect.
+    pub fn disconnect_controller(&mut self, controller_id: &Uuid) {
+        for session in &mut self.sessions {
+            if session.controller_id.read().expect("reading controller_id").as_ref() == Some(controller_id) {
+                if let Some(sender) = session.shutdown_sender.take() {
+                    info!("disconnecting session {} of controller {}", session.id, controller_id);
+                    let _ = sender.send(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use futures::lock::Mutex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_registry() {
+        let mut registry = SessionRegistry::new();
+        let peer_addr = "127.0.0.1:1234".parse().unwrap();
+        let controller_id = Uuid::parse_str("bc158b86-cabf-432d-aee4-422ef0e3f1d5").unwrap();
+
+        let mut receivers = Vec::new();
+        for _ in 0..MAX_SESSIONS {
+            let (id, receiver) = registry
+                .register(peer_addr, Arc::new(RwLock::new(None)), Arc::new(Mutex::new(vec![])))
+                .unwrap();
+            receivers.push((id, receiver));
+        }
+        assert!(registry
+            .register(peer_addr, Arc::new(RwLock::new(None)), Arc::new(Mutex::new(vec![])))
+            .is_err());
+
+        let (id, _) = receivers.remove(0);
+        registry.unregister(id);
+        assert!(registry.disconnect(id).is_err());
+
+        let (id, mut receiver) = registry
+            .register(
+                peer_addr,
+                Arc::new(RwLock::new(Some(controller_id))),
+                Arc::new(Mutex::new(vec![(1, 9)])),
+            )
+            .unwrap();
+        let sessions = registry.list().await;
+        let session = sessions.iter().find(|s| s.id == id).unwrap();
+        assert_eq!(session.controller_id, Some(controller_id));
+        assert_eq!(session.subscriptions, vec![(1, 9)]);
+
+        assert_eq!(receiver.try_recv().unwrap(), None);
+        registry.disconnect_controller(&controller_id);
+        assert_eq!(receiver.try_recv().unwrap(), Some(()));
+        assert_eq!(receivers[0].1.try_recv().unwrap(), None);
+    }
+}
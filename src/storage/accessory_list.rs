@@ -1,18 +1,24 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
-use futures::lock::Mutex;
+use byteorder::{BigEndian, ByteOrder};
 use log::debug;
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
 use crate::{
     accessory::HapAccessory,
-    characteristic::Perm,
+    characteristic::{CallbackContext, Perm},
+    event::Event,
     pointer,
     transport::http::{ReadResponseObject, Status, WriteObject, WriteResponseObject},
     Error,
+    HapType,
     Result,
 };
 
+/// Maximum number of Accessories a bridge is allowed to expose, including the bridge itself.
+const MAX_ACCESSORIES: usize = 150;
+
 // TODO: rename to AccessoryDatabase?
 /// `AccessoryList` is a wrapper type holding a list of Accessories.
 pub struct AccessoryList {
@@ -29,39 +35,61 @@ impl AccessoryList {
         }
     }
 
-    /// Adds an Accessory to the `AccessoryList` and returns a pointer to the added Accessory.
-    pub fn add_accessory(&mut self, accessory: Box<dyn HapAccessory>) -> Result<pointer::Accessory> {
-        let mut accessory = accessory;
-        accessory.set_event_emitter_on_characteristics(Some(self.event_emitter.clone()));
+    /// Adds an Accessory to the `AccessoryList`.
+    ///
+    /// The Accessory is validated before it's added. It's rejected if its ID is already taken, if it's missing an
+    /// Accessory Information Service, if its Services and Characteristics don't have unique instance IDs or if the
+    /// `AccessoryList` already holds the maximum number of Accessories.
+    pub async fn add_accessory(&mut self, accessory: pointer::Accessory) -> Result<()> {
+        if self.accessories.len() >= MAX_ACCESSORIES {
+            return Err(Error::MaxAccessories);
+        }
+
+        let mut a = accessory.lock().await;
+
+        let aid = a.get_id();
+        if aid == 0 {
+            return Err(Error::InvalidAccessoryId);
+        }
+        for other in &self.accessories {
+            if other.lock().await.get_id() == aid {
+                return Err(Error::DuplicateAccessory);
+            }
+        }
+
+        validate_accessory(&*a)?;
+
+        a.set_event_emitter_on_characteristics(Some(self.event_emitter.clone()));
+        drop(a);
+
+        self.accessories.push(accessory);
 
-        let accessory = Arc::new(Mutex::new(accessory));
-        self.accessories.push(accessory.clone());
-        // TODO: some error handling here?
+        self.event_emitter.lock().await.emit(&Event::AccessoryAdded { aid });
 
-        Ok(accessory)
+        Ok(())
     }
 
     /// Takes a pointer to an Accessory and removes the Accessory from the `AccessoryList`.
+    ///
+    /// The event emitters on the Characteristics of the removed Accessory are detached and all event subscriptions of
+    /// controllers on them are dropped.
     pub async fn remove_accessory(&mut self, accessory: &pointer::Accessory) -> Result<()> {
-        let accessory = accessory.lock().await;
-        let mut remove = None;
+        let position = self.accessories.iter().position(|a| Arc::ptr_eq(a, accessory));
 
-        for (i, a) in self.accessories.iter_mut().enumerate() {
-            if a.lock().await.get_id() == accessory.get_id() {
-                a.lock().await.set_event_emitter_on_characteristics(None);
+        match position {
+            Some(i) => {
+                let accessory = self.accessories.remove(i);
+                let mut a = accessory.lock().await;
+                a.set_event_emitter_on_characteristics(None);
+                let aid = a.get_id();
+                drop(a);
 
-                remove = Some(i);
-                break;
-            }
-        }
+                self.event_emitter.lock().await.emit(&Event::AccessoryRemoved { aid });
 
-        if let Some(i) = remove {
-            self.accessories.remove(i);
-
-            return Ok(());
+                Ok(())
+            },
+            None => Err(Error::AccessoryNotFound),
         }
-
-        Err(Error::AccessoryNotFound)
     }
 
     pub(crate) async fn read_characteristic(
@@ -72,6 +100,7 @@ impl AccessoryList {
         perms: bool,
         hap_type: bool,
         ev: bool,
+        context: &CallbackContext,
     ) -> Result<ReadResponseObject> {
         let mut result_object = ReadResponseObject {
             iid,
@@ -89,39 +118,35 @@ impl AccessoryList {
             status: Some(0),
         };
 
-        'l: for accessory in self.accessories.iter() {
+        for accessory in self.accessories.iter() {
             let mut a = accessory.lock().await;
             if a.get_id() == aid {
-                for service in a.get_mut_services() {
-                    for characteristic in service.get_mut_characteristics() {
-                        if characteristic.get_id() == iid {
-                            let characteristic_perms = characteristic.get_perms();
-                            if characteristic_perms.contains(&Perm::PairedRead) {
-                                result_object.value = Some(characteristic.get_value().await?);
-                                if meta {
-                                    result_object.format = Some(characteristic.get_format());
-                                    result_object.unit = characteristic.get_unit();
-                                    result_object.max_value = characteristic.get_max_value();
-                                    result_object.min_value = characteristic.get_min_value();
-                                    result_object.step_value = characteristic.get_step_value();
-                                    result_object.max_len = characteristic.get_max_len();
-                                }
-                                if perms {
-                                    result_object.perms = Some(characteristic_perms);
-                                }
-                                if hap_type {
-                                    result_object.hap_type = Some(characteristic.get_type());
-                                }
-                                if ev {
-                                    result_object.ev = characteristic.get_event_notifications();
-                                }
-                            } else {
-                                result_object.status = Some(Status::WriteOnlyCharacteristic as i32);
-                            }
-                            break 'l;
+                if let Some(characteristic) = a.get_mut_characteristic_by_id(iid) {
+                    let characteristic_perms = characteristic.get_perms();
+                    if characteristic_perms.contains(&Perm::PairedRead) {
+                        result_object.value = Some(characteristic.get_value_with_context(context).await?);
+                        if meta {
+                            result_object.format = Some(characteristic.get_format());
+                            result_object.unit = characteristic.get_unit();
+                            result_object.max_value = characteristic.get_max_value();
+                            result_object.min_value = characteristic.get_min_value();
+                            result_object.step_value = characteristic.get_step_value();
+                            result_object.max_len = characteristic.get_max_len();
+                        }
+                        if perms {
+                            result_object.perms = Some(characteristic_perms);
+                        }
+                        if hap_type {
+                            result_object.hap_type = Some(characteristic.get_type());
                         }
+                        if ev {
+                            result_object.ev = characteristic.get_event_notifications();
+                        }
+                    } else {
+                        result_object.status = Some(Status::WriteOnlyCharacteristic as i32);
                     }
                 }
+                break;
             }
         }
 
@@ -132,6 +157,7 @@ impl AccessoryList {
         &mut self,
         write_object: WriteObject,
         event_subscriptions: &pointer::EventSubscriptions,
+        context: &CallbackContext,
     ) -> Result<WriteResponseObject> {
         let mut result_object = WriteResponseObject {
             aid: write_object.aid,
@@ -139,43 +165,51 @@ impl AccessoryList {
             status: 0,
         };
 
-        'l: for accessory in self.accessories.iter_mut() {
+        for accessory in self.accessories.iter_mut() {
             let mut a = accessory.lock().await;
             if a.get_id() == write_object.aid {
-                for service in a.get_mut_services() {
-                    for characteristic in service.get_mut_characteristics() {
-                        if characteristic.get_id() == write_object.iid {
-                            let characteristic_perms = characteristic.get_perms();
-                            if let Some(ev) = write_object.ev {
-                                if characteristic_perms.contains(&Perm::Events) {
-                                    characteristic.set_event_notifications(Some(ev));
-                                    let subscription = (write_object.aid, write_object.iid);
-                                    let mut es = event_subscriptions.lock().await;
-                                    let pos = es.iter().position(|&s| s == subscription);
-                                    match (ev, pos) {
-                                        (true, None) => {
-                                            es.push(subscription);
-                                        },
-                                        (false, Some(p)) => {
-                                            es.remove(p);
-                                        },
-                                        _ => {},
-                                    }
-                                } else {
-                                    result_object.status = Status::NotificationNotSupported as i32;
-                                }
+                if let Some(characteristic) = a.get_mut_characteristic_by_id(write_object.iid) {
+                    let characteristic_perms = characteristic.get_perms();
+                    if let Some(ev) = write_object.ev {
+                        if characteristic_perms.contains(&Perm::Events) {
+                            characteristic.set_event_notifications(Some(ev));
+                            let subscription = (write_object.aid, write_object.iid);
+                            let mut es = event_subscriptions.lock().await;
+                            let pos = es.iter().position(|&s| s == subscription);
+                            match (ev, pos) {
+                                (true, None) => {
+                                    es.push(subscription);
+                                },
+                                (false, Some(p)) => {
+                                    es.remove(p);
+                                },
+                                _ => {},
                             }
-                            if let Some(value) = write_object.value {
-                                if characteristic_perms.contains(&Perm::PairedWrite) {
-                                    characteristic.set_value(value).await?;
-                                } else {
-                                    result_object.status = Status::ReadOnlyCharacteristic as i32;
-                                }
+                        } else {
+                            result_object.status = Status::NotificationNotSupported as i32;
+                        }
+                    }
+                    if let Some(value) = write_object.value {
+                        if characteristic_perms.contains(&Perm::PairedWrite) {
+                            characteristic
+                                .set_value_with_context(value, &CallbackContext {
+                                    remote: write_object.remote.unwrap_or(false),
+                                    ..context.clone()
+                                })
+                                .await?;
+
+                            if characteristic.get_type() == HapType::Identify {
+                                self.event_emitter
+                                    .lock()
+                                    .await
+                                    .emit(&Event::IdentifyRequested { aid: write_object.aid });
                             }
-                            break 'l;
+                        } else {
+                            result_object.status = Status::ReadOnlyCharacteristic as i32;
                         }
                     }
                 }
+                break;
             }
         }
 
@@ -195,12 +229,213 @@ impl AccessoryList {
 
         Ok(serde_json::to_vec(&json)?)
     }
+
+    /// Returns a hash of the structure of the accessory database. The hash covers the types, IDs, perms and metadata of
+    /// all Accessories, Services and Characteristics, but not the values of the Characteristics.
+    pub(crate) async fn structure_hash(&self) -> Result<u64> {
+        let mut accessory_values = Vec::new();
+        for accessory in &self.accessories {
+            let a = accessory.lock().await;
+            let mut accessory_value = serde_json::to_value(&*a)?;
+            strip_characteristic_values(&mut accessory_value);
+            accessory_values.push((a.get_id(), accessory_value));
+        }
+        accessory_values.sort_by_key(|&(aid, _)| aid);
+
+        let structure = accessory_values.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+        let digest = Sha256::digest(&serde_json::to_vec(&structure)?);
+
+        Ok(BigEndian::read_u64(&digest[..8]))
+    }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
+/// Checks that an Accessory has an Accessory Information Service, that the instance IDs of its Services and
+/// Characteristics are unique and that linked Services refer to other Services of the Accessory.
+fn validate_accessory(accessory: &dyn HapAccessory) -> Result<()> {
+    if accessory.get_service(HapType::AccessoryInformation).is_none() {
+        return Err(Error::MissingAccessoryInformation);
+    }
+
+    let mut iids = HashSet::new();
+    for service in accessory.get_services() {
+        if !iids.insert(service.get_id()) {
+            return Err(Error::DuplicateInstanceId(service.get_id()));
+        }
+        for characteristic in service.get_characteristics() {
+            if !iids.insert(characteristic.get_id()) {
+                return Err(Error::DuplicateInstanceId(characteristic.get_id()));
+            }
+        }
+    }
 
-//     #[test]
-//     fn test_json_serialization() {} // TODO: test it
-// }
+    let services = accessory.get_services();
+    for service in &services {
+        for linked_id in service.get_linked_services() {
+            if linked_id == service.get_id() || !services.iter().any(|s| s.get_id() == linked_id) {
+                return Err(Error::InvalidLinkedService(linked_id));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the current values and event notification states from the Characteristics of a serialized Accessory.
+fn strip_characteristic_values(accessory: &mut serde_json::Value) {
+    if let Some(services) = accessory.get_mut("services").and_then(|s| s.as_array_mut()) {
+        for service in services {
+            if let Some(characteristics) = service.get_mut("characteristics").and_then(|c| c.as_array_mut()) {
+                for characteristic in characteristics.iter_mut().filter_map(|c| c.as_object_mut()) {
+                    characteristic.remove("value");
+                    characteristic.remove("ev");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::lock::Mutex;
+
+    use super::*;
+    use crate::{
+        accessory::{
+            bridge::BridgeAccessory,
+            lightbulb::LightbulbAccessory,
+            lock::LockAccessory,
+            programmable_switch::{ProgrammableSwitchAccessory, ServiceLabelNamespace},
+            television::TelevisionAccessory,
+            AccessoryInformation,
+            HapAccessory,
+        },
+        event::EventEmitter,
+        service::{lightbulb::LightbulbService, HapService},
+    };
+
+    #[derive(HapAccessory)]
+    struct UnidentifiedAccessory {
+        id: u64,
+        #[service]
+        lightbulb: LightbulbService,
+    }
+
+    // #[test]
+    // fn test_json_serialization() {} // TODO: test it
+
+    #[tokio::test]
+    async fn test_structure_hash() {
+        let mut accessory_list = AccessoryList::new(Arc::new(Mutex::new(EventEmitter::new())));
+        let lightbulb: pointer::Accessory = Arc::new(Mutex::new(
+            LightbulbAccessory::new(1, AccessoryInformation::default()).unwrap(),
+        ));
+        accessory_list.add_accessory(lightbulb.clone()).await.unwrap();
+
+        let hash = accessory_list.structure_hash().await.unwrap();
+
+        lightbulb
+            .lock()
+            .await
+            .get_mut_service(crate::HapType::Lightbulb)
+            .unwrap()
+            .get_mut_characteristic(crate::HapType::On)
+            .unwrap()
+            .set_value(json!(true))
+            .await
+            .unwrap();
+        assert_eq!(accessory_list.structure_hash().await.unwrap(), hash);
+
+        let second_lightbulb = LightbulbAccessory::new(2, AccessoryInformation::default()).unwrap();
+        accessory_list
+            .add_accessory(Arc::new(Mutex::new(second_lightbulb)))
+            .await
+            .unwrap();
+        assert_ne!(accessory_list.structure_hash().await.unwrap(), hash);
+    }
+
+    #[tokio::test]
+    async fn test_add_accessory_validation() {
+        let mut accessory_list = AccessoryList::new(Arc::new(Mutex::new(EventEmitter::new())));
+        let information = || AccessoryInformation {
+            hardware_revision: Some("1.0.0".into()),
+            accessory_flags: Some(0),
+            ..Default::default()
+        };
+
+        accessory_list
+            .add_accessory(Arc::new(Mutex::new(BridgeAccessory::new(1, information()).unwrap())))
+            .await
+            .unwrap();
+        accessory_list
+            .add_accessory(Arc::new(Mutex::new(LockAccessory::new(2, information()).unwrap())))
+            .await
+            .unwrap();
+        accessory_list
+            .add_accessory(Arc::new(Mutex::new(
+                TelevisionAccessory::new(3, information()).unwrap(),
+            )))
+            .await
+            .unwrap();
+        accessory_list
+            .add_accessory(Arc::new(Mutex::new(
+                ProgrammableSwitchAccessory::new(5, information(), 8, ServiceLabelNamespace::ArabicNumerals).unwrap(),
+            )))
+            .await
+            .unwrap();
+
+        match accessory_list
+            .add_accessory(Arc::new(Mutex::new(LightbulbAccessory::new(2, information()).unwrap())))
+            .await
+        {
+            Err(Error::DuplicateAccessory) => {},
+            _ => panic!("expected a duplicate accessory error"),
+        }
+        match accessory_list
+            .add_accessory(Arc::new(Mutex::new(LightbulbAccessory::new(0, information()).unwrap())))
+            .await
+        {
+            Err(Error::InvalidAccessoryId) => {},
+            _ => panic!("expected an invalid accessory ID error"),
+        }
+
+        let mut lightbulb = LightbulbAccessory::new(4, information()).unwrap();
+        lightbulb.lightbulb.set_linked_services(vec![99]);
+        match accessory_list.add_accessory(Arc::new(Mutex::new(lightbulb))).await {
+            Err(Error::InvalidLinkedService(99)) => {},
+            _ => panic!("expected an invalid linked service error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_accessory_information() {
+        let mut accessory_list = AccessoryList::new(Arc::new(Mutex::new(EventEmitter::new())));
+        let accessory = UnidentifiedAccessory {
+            id: 1,
+            lightbulb: LightbulbService::new(1, 1),
+        };
+
+        match accessory_list.add_accessory(Arc::new(Mutex::new(accessory))).await {
+            Err(Error::MissingAccessoryInformation) => {},
+            _ => panic!("expected a missing accessory information error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_accessories() {
+        let mut accessory_list = AccessoryList::new(Arc::new(Mutex::new(EventEmitter::new())));
+        for aid in 1..=MAX_ACCESSORIES as u64 {
+            accessory_list
+                .add_accessory(Arc::new(Mutex::new(
+                    LightbulbAccessory::new(aid, AccessoryInformation::default()).unwrap(),
+                )))
+                .await
+                .unwrap();
+        }
+
+        let lightbulb = LightbulbAccessory::new(MAX_ACCESSORIES as u64 + 1, AccessoryInformation::default()).unwrap();
+        match accessory_list.add_accessory(Arc::new(Mutex::new(lightbulb))).await {
+            Err(Error::MaxAccessories) => {},
+            _ => panic!("expected a max accessories error"),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::io::ErrorKind;
+
+use ed25519_dalek::Keypair as Ed25519Keypair;
+use eui48::MacAddress;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use signature::{Signer, Verifier};
+
+use crate::{
+    pairing::Pairing,
+    storage::{
+        encryption::{self, EncryptionKey},
+        Storage,
+    },
+    BonjourStatusFlag,
+    Config,
+    Ed25519PublicKey,
+    Error,
+    Pin,
+    Result,
+    SetupVerifier,
+};
+
+/// Version of the backup bundle format.
+const BACKUP_VERSION: u32 = 1;
+
+/// What an imported backup bundle is authenticated with. A bundle is only imported if it was created by someone
+/// holding the passphrase or the private key, never on the strength of the keypair the bundle carries itself.
+#[derive(Debug, Clone, Copy)]
+pub enum BackupAuthentication<'a> {
+    /// The passphrase an encrypted bundle was exported with.
+    Passphrase(&'a str),
+    /// The public key of the device an unencrypted bundle was exported from, e.g. taken from its `Config` beforehand.
+    PublicKey(Ed25519PublicKey),
+}
+
+/// The outer envelope of a backup bundle.
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    version: u32,
+    /// Salt of the passphrase the payload is encrypted with, if it's encrypted.
+    salt: Option<[u8; 16]>,
+    /// The serialized `SignedContents`, possibly encrypted.
+    payload: Vec<u8>,
+}
+
+/// The serialized `Contents` along with their signature by the device's Ed25519 keypair.
+#[derive(Serialize, Deserialize)]
+struct SignedContents {
+    contents: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+/// The contents of a backup bundle. There are no identifier maps, as the AIDs and IIDs are assigned by the application.
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    identity: Identity,
+    pairings: Vec<Pairing>,
+}
+
+/// The parts of the `Config` that make up the identity of the accessory server towards paired controllers.
+#[derive(Serialize, Deserialize)]
+struct Identity {
+    device_id: MacAddress,
+    device_ed25519_keypair: Ed25519Keypair,
+    pin: Option<Pin>,
+    setup_verifier: Option<SetupVerifier>,
+    configuration_number: u64,
+    configuration_hash: Option<u64>,
+}
+
+/// Exports the identity of an accessory server, i.e. its device ID, keypair, setup data and configuration number, and
+/// all of its `Pairing`s from a `Storage` into a backup bundle. The bundle is signed with the device's keypair and, if
+/// a passphrase is given, encrypted with a key derived from it. An encrypted bundle is imported with the passphrase,
+/// an unencrypted one with the device's public key, see [`BackupAuthentication`].
+///
+/// Restoring the bundle with [`import_backup`] on a replacement device lets it take over without re-pairing any
+/// controllers. As the bundle holds the device's private key, it should be kept as secret as the storage itself.
+///
+/// Identifier maps aren't part of the bundle, as the accessory server doesn't keep any: the AIDs and IIDs are the
+/// ones the application assigns to its Accessories. The replacement device has to add the same Accessories with the
+/// same IDs, otherwise controllers see them as new ones.
+pub async fn export_backup<S: Storage + ?Sized>(storage: &S, passphrase: Option<&str>) -> Result<Vec<u8>> {
+    let config = storage.load_config().await?;
+    let contents = Contents {
+        identity: Identity {
+            device_id: config.device_id,
+            device_ed25519_keypair: config.device_ed25519_keypair,
+            pin: config.pin,
+            setup_verifier: config.setup_verifier,
+            configuration_number: config.configuration_number,
+            configuration_hash: config.configuration_hash,
+        },
+        pairings: storage.list_pairings().await?,
+    };
+
+    let contents_bytes = serde_json::to_vec(&contents)?;
+    let signature = contents.identity.device_ed25519_keypair.sign(&contents_bytes);
+    let signed_contents = SignedContents {
+        contents: contents_bytes,
+        signature: signature.to_bytes().to_vec(),
+    };
+    let mut payload = serde_json::to_vec(&signed_contents)?;
+
+    let mut salt = None;
+    if let Some(passphrase) = passphrase {
+        let mut csprng = OsRng {};
+        let mut s = [0; 16];
+        csprng.fill_bytes(&mut s);
+
+        payload = encryption::seal(&EncryptionKey::from_passphrase(passphrase, &s), &payload)?;
+        salt = Some(s);
+    }
+
+    let bundle = Bundle {
+        version: BACKUP_VERSION,
+        salt,
+        payload,
+    };
+
+    Ok(serde_json::to_vec(&bundle)?)
+}
+
+/// Imports a backup bundle created with [`export_backup`] into a `Storage`. The identity in the stored `Config` is
+/// replaced with the one from the bundle, other settings of the `Config` are kept, and all stored `Pairing`s are
+/// replaced with the ones from the bundle.
+///
+/// An encrypted bundle is authenticated by decrypting it with the passphrase, an unencrypted one by checking its
+/// signature against the given public key. A bundle that can't be authenticated that way is rejected.
+///
+/// The bundle is fully decoded and checked before anything is written, and the `Config` and `Pairing`s are replaced
+/// at once with [`Storage::save_config_and_pairings`], so a failed import leaves the `Storage` as it was.
+///
+/// The bundle should be imported before an `IpServer` is created from the `Storage`.
+pub async fn import_backup<S: Storage + ?Sized>(
+    storage: &mut S,
+    bundle: &[u8],
+    authentication: BackupAuthentication<'_>,
+) -> Result<()> {
+    let bundle: Bundle = serde_json::from_slice(bundle).map_err(|_| Error::InvalidBackup)?;
+    if bundle.version != BACKUP_VERSION {
+        return Err(Error::InvalidBackup);
+    }
+
+    let payload = match (bundle.salt, authentication) {
+        (Some(salt), BackupAuthentication::Passphrase(passphrase)) =>
+            encryption::open(&EncryptionKey::from_passphrase(passphrase, &salt), &bundle.payload)?,
+        (Some(_), BackupAuthentication::PublicKey(_)) => return Err(Error::BackupPassphraseRequired),
+        (None, BackupAuthentication::PublicKey(_)) => bundle.payload,
+        (None, BackupAuthentication::Passphrase(_)) => return Err(Error::BackupPublicKeyRequired),
+    };
+
+    let signed_contents: SignedContents = serde_json::from_slice(&payload).map_err(|_| Error::InvalidBackup)?;
+    let contents: Contents = serde_json::from_slice(&signed_contents.contents).map_err(|_| Error::InvalidBackup)?;
+    let public_key = contents.identity.device_ed25519_keypair.public;
+    if let BackupAuthentication::PublicKey(trusted_public_key) = authentication {
+        if public_key != trusted_public_key {
+            return Err(Error::InvalidBackup);
+        }
+    }
+    let signature =
+        ed25519_dalek::Signature::from_bytes(&signed_contents.signature).map_err(|_| Error::InvalidBackup)?;
+    public_key
+        .verify(&signed_contents.contents, &signature)
+        .map_err(|_| Error::InvalidBackup)?;
+
+    let mut config = match storage.load_config().await {
+        Ok(config) => config,
+        Err(Error::Io(ref e)) if e.kind() == ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(e),
+    };
+
+    let identity = contents.identity;
+    config.device_id = identity.device_id;
+    config.device_ed25519_keypair = identity.device_ed25519_keypair;
+    config.pin = identity.pin;
+    config.setup_verifier = identity.setup_verifier;
+    config.configuration_number = identity.configuration_number;
+    config.configuration_hash = identity.configuration_hash;
+    config.status_flag = if contents.pairings.is_empty() {
+        BonjourStatusFlag::NotPaired
+    } else {
+        BonjourStatusFlag::Zero
+    };
+
+    storage.save_config_and_pairings(&config, &contents.pairings).await
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        pairing::Permissions,
+        storage::{FileStorage, MemoryStorage},
+    };
+
+    #[tokio::test]
+    async fn test_backup_roundtrip() {
+        let mut storage = MemoryStorage::new();
+        let config = Config {
+            configuration_number: 7,
+            ..Default::default()
+        };
+        let device_id = config.device_id;
+        let public_key = config.device_ed25519_keypair.public;
+        storage.save_config(&config).await.unwrap();
+        let pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [6; 32]);
+        storage.save_pairing(&pairing).await.unwrap();
+
+        let bundle = export_backup(&storage, Some("correct horse")).await.unwrap();
+
+        let mut replacement = MemoryStorage::new();
+        replacement
+            .save_pairing(&Pairing::new(Uuid::new_v4(), Permissions::User, [7; 32]))
+            .await
+            .unwrap();
+        assert!(
+            import_backup(&mut replacement, &bundle, BackupAuthentication::PublicKey(public_key))
+                .await
+                .is_err()
+        );
+        assert!(import_backup(
+            &mut replacement,
+            &bundle,
+            BackupAuthentication::Passphrase("wrong horse")
+        )
+        .await
+        .is_err());
+        import_backup(
+            &mut replacement,
+            &bundle,
+            BackupAuthentication::Passphrase("correct horse"),
+        )
+        .await
+        .unwrap();
+
+        let restored = replacement.load_config().await.unwrap();
+        assert_eq!(restored.device_id, device_id);
+        assert_eq!(restored.device_ed25519_keypair.public, public_key);
+        assert_eq!(restored.configuration_number, 7);
+        assert_eq!(replacement.list_pairings().await.unwrap(), vec![pairing]);
+
+        let unencrypted = export_backup(&storage, None).await.unwrap();
+        assert!(import_backup(
+            &mut MemoryStorage::new(),
+            &unencrypted,
+            BackupAuthentication::Passphrase("")
+        )
+        .await
+        .is_err());
+        import_backup(
+            &mut MemoryStorage::new(),
+            &unencrypted,
+            BackupAuthentication::PublicKey(public_key),
+        )
+        .await
+        .unwrap();
+
+        let mut tampered: Bundle = serde_json::from_slice(&export_backup(&storage, None).await.unwrap()).unwrap();
+        let mut signed_contents: SignedContents = serde_json::from_slice(&tampered.payload).unwrap();
+        let mut contents: serde_json::Value = serde_json::from_slice(&signed_contents.contents).unwrap();
+        contents["identity"]["configuration_number"] = 8.into();
+        signed_contents.contents = serde_json::to_vec(&contents).unwrap();
+        tampered.payload = serde_json::to_vec(&signed_contents).unwrap();
+        let tampered = serde_json::to_vec(&tampered).unwrap();
+        assert!(import_backup(
+            &mut MemoryStorage::new(),
+            &tampered,
+            BackupAuthentication::PublicKey(public_key)
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forged_backup() {
+        let config = Config::default();
+        let public_key = config.device_ed25519_keypair.public;
+
+        // a bundle signed with another keypair that carries that keypair along
+        let mut forger = MemoryStorage::new();
+        forger.save_config(&Config::default()).await.unwrap();
+        forger
+            .save_pairing(&Pairing::new(Uuid::new_v4(), Permissions::Admin, [8; 32]))
+            .await
+            .unwrap();
+        let forged = export_backup(&forger, None).await.unwrap();
+
+        let mut storage = MemoryStorage::new();
+        storage.save_config(&config).await.unwrap();
+        assert!(
+            import_backup(&mut storage, &forged, BackupAuthentication::PublicKey(public_key))
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            storage.load_config().await.unwrap().device_ed25519_keypair.public,
+            public_key
+        );
+        assert_eq!(storage.count_pairings().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_import() {
+        let dir = std::env::temp_dir().join(format!("hap-backup-test-{}", Uuid::new_v4()));
+        let mut storage = FileStorage::new(&dir).await.unwrap();
+        let config = Config::default();
+        let device_id = config.device_id;
+        storage.save_config(&config).await.unwrap();
+        let pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [6; 32]);
+        storage.save_pairing(&pairing).await.unwrap();
+
+        let mut source = MemoryStorage::new();
+        source.save_config(&Config::default()).await.unwrap();
+        let bundle = export_backup(&source, Some("correct horse")).await.unwrap();
+
+        assert!(
+            import_backup(&mut storage, &bundle, BackupAuthentication::Passphrase("wrong horse"))
+                .await
+                .is_err()
+        );
+        assert_eq!(storage.load_config().await.unwrap().device_id, device_id);
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![pairing.clone()]);
+
+        // a file in place of the staging directory makes the import fail before anything is replaced
+        std::fs::write(dir.join("staging.tmp"), b"").unwrap();
+        assert!(
+            import_backup(&mut storage, &bundle, BackupAuthentication::Passphrase("correct horse"))
+                .await
+                .is_err()
+        );
+        assert_eq!(storage.load_config().await.unwrap().device_id, device_id);
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![pairing]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
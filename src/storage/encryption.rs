@@ -0,0 +1,133 @@
+use std::{env, fmt, fs, num::NonZeroU32, path::PathBuf};
+
+use aead::{generic_array::GenericArray, AeadInPlace, NewAead};
+use chacha20poly1305::ChaCha20Poly1305;
+use rand::{rngs::OsRng, RngCore};
+use ring::{digest, pbkdf2};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// Marks data encrypted with `seal`, so plaintext data written by earlier versions can be told apart.
+const MAGIC: &[u8] = b"hap-sealed-v1\0";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Source of the secret the key for encrypting secret material at rest is derived from. The secret should contain at
+/// least 32 bytes of random data.
+pub enum KeySource {
+    /// Reads the secret from a file.
+    File(PathBuf),
+    /// Reads the secret from an environment variable.
+    Env(String),
+    /// Calls back into the application for the secret, e.g. to fetch it from a hardware keystore.
+    Callback(Box<dyn Fn() -> Result<Vec<u8>> + Send + Sync>),
+}
+
+impl fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KeySource::File(ref path) => f.debug_tuple("File").field(path).finish(),
+            KeySource::Env(ref name) => f.debug_tuple("Env").field(name).finish(),
+            KeySource::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+impl KeySource {
+    /// Reads the secret and derives the encryption key from it.
+    pub(crate) fn resolve(&self) -> Result<EncryptionKey> {
+        let secret = match *self {
+            KeySource::File(ref path) => fs::read(path)?,
+            KeySource::Env(ref name) => env::var(name)
+                .map_err(|_| Error::EncryptionKeyUnavailable(name.clone()))?
+                .into_bytes(),
+            KeySource::Callback(ref f) => f()?,
+        };
+        if secret.is_empty() {
+            return Err(Error::EncryptionKeyUnavailable(format!("{:?}", self)));
+        }
+
+        let mut key = [0; 32];
+        key.copy_from_slice(&Sha256::digest(&secret));
+
+        Ok(EncryptionKey(key))
+    }
+}
+
+/// A key for encrypting secret material at rest.
+#[derive(Clone)]
+pub(crate) struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Derives a key from a passphrase with PBKDF2-HMAC-SHA256.
+    pub(crate) fn from_passphrase(passphrase: &str, salt: &[u8]) -> Self {
+        let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).expect("PBKDF2 iterations must be non-zero");
+        let mut key = [0; 32];
+        pbkdf2::derive(&digest::SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+
+        EncryptionKey(key)
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str("EncryptionKey(..)") }
+}
+
+/// Returns whether the data was encrypted with `seal`.
+pub(crate) fn is_sealed(data: &[u8]) -> bool { data.starts_with(MAGIC) }
+
+/// Encrypts the data with a random nonce.
+pub(crate) fn seal(key: &EncryptionKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut csprng = OsRng {};
+    let mut nonce = [0; NONCE_LEN];
+    csprng.fill_bytes(&mut nonce);
+
+    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&key.0));
+    let mut ciphertext = plaintext.to_vec();
+    let auth_tag = aead.encrypt_in_place_detached(GenericArray::from_slice(&nonce), MAGIC, &mut ciphertext)?;
+
+    Ok([MAGIC, &nonce[..], &auth_tag[..], &ciphertext[..]].concat())
+}
+
+/// Decrypts data encrypted with `seal`.
+pub(crate) fn open(key: &EncryptionKey, data: &[u8]) -> Result<Vec<u8>> {
+    if !is_sealed(data) || data.len() < MAGIC.len() + NONCE_LEN + TAG_LEN {
+        return Err(Error::Aead);
+    }
+    let data = &data[MAGIC.len()..];
+    let (nonce, data) = data.split_at(NONCE_LEN);
+    let (auth_tag, ciphertext) = data.split_at(TAG_LEN);
+
+    let aead = ChaCha20Poly1305::new(GenericArray::from_slice(&key.0));
+    let mut plaintext = ciphertext.to_vec();
+    aead.decrypt_in_place_detached(
+        GenericArray::from_slice(nonce),
+        MAGIC,
+        &mut plaintext,
+        GenericArray::from_slice(auth_tag),
+    )?;
+
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = KeySource::Callback(Box::new(|| Ok(b"0123456789abcdef0123456789abcdef".to_vec())))
+            .resolve()
+            .unwrap();
+        let other_key = KeySource::Callback(Box::new(|| Ok(b"another secret".to_vec())))
+            .resolve()
+            .unwrap();
+
+        let sealed = seal(&key, b"secret material").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open(&key, &sealed).unwrap(), b"secret material");
+        assert!(open(&other_key, &sealed).is_err());
+    }
+}
@@ -2,21 +2,71 @@ use std::{
     env,
     ffi::OsStr,
     fs,
-    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    io::{self, BufReader, ErrorKind, Read, Write},
     path::{Path, PathBuf},
     str,
 };
 
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
 use async_trait::async_trait;
+use log::info;
 use tokio::task::spawn_blocking;
 use uuid::Uuid;
 
-use crate::{pairing::Pairing, storage::Storage, Config, Error, Result};
+use crate::{
+    pairing::Pairing,
+    storage::{
+        encryption::{self, EncryptionKey, KeySource},
+        storage::validate_data_key,
+        Storage,
+    },
+    Config,
+    Error,
+    Result,
+};
+
+/// Version of the directory layout written by `FileStorage`. The flat layout of earlier versions, with `config.json`
+/// and all pairings in the storage directory itself, is considered version 0.
+const SCHEMA_VERSION: u32 = 1;
+
+const VERSION_FILE: &str = "version";
+const CONFIG_DIR: &str = "config";
+const PAIRINGS_DIR: &str = "pairings";
+const VALUES_DIR: &str = "values";
+const APP_DIR: &str = "app";
+/// Staging directory of the `Config` and `Pairing`s written by `save_config_and_pairings`.
+const STAGING_DIR: &str = "staging.tmp";
+/// The replaced `Pairing`s, kept until the staged `Config` is in place.
+const OLD_PAIRINGS_DIR: &str = "pairings.old";
 
 /// `FileStorage` is an implementor of the `Storage` trait that stores data to the file system.
+///
+/// The storage directory is laid out as follows:
+///
+/// ```text
+/// version                  schema version of the layout
+/// config/config.json       the `Config`
+/// pairings/<uuid>.json     one file per `Pairing`
+/// values/<aid>.<iid>.json  persisted Characteristic values
+/// app/<namespace>/<key>    application data
+/// ```
+///
+/// A storage directory in the flat layout of earlier versions is migrated automatically.
+///
+/// `save_config_and_pairings` writes the new `Config` and `Pairing`s to a staging directory first, swaps the pairings
+/// directory and commits by moving the staged `Config` into place. An interrupted swap is completed or rolled back
+/// when the `FileStorage` is created again.
+///
+/// All files are created readable and writable by the owner only. To keep the secret material of the `Config`, i.e. the
+/// device keypair and the setup PIN, from being usable when the storage directory is copied off the device, the
+/// `Config` can additionally be encrypted with a key supplied by the application using
+/// [`with_encryption`](FileStorage::with_encryption).
 #[derive(Debug)]
 pub struct FileStorage {
     dir_path: PathBuf,
+    encryption_key: Option<EncryptionKey>,
 }
 
 impl FileStorage {
@@ -26,11 +76,48 @@ impl FileStorage {
         let dir_path = spawn_blocking(move || -> Result<PathBuf> {
             fs::create_dir_all(&dir_path)?;
 
+            let version_path = dir_path.join(VERSION_FILE);
+            let version = match fs::read_to_string(&version_path) {
+                Ok(version) => version.trim().parse()?,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => 0,
+                Err(e) => return Err(e.into()),
+            };
+            if version > SCHEMA_VERSION {
+                return Err(Error::UnsupportedStorageVersion(version));
+            }
+
+            recover_staged_pairings(&dir_path)?;
+
+            for namespace in &[CONFIG_DIR, PAIRINGS_DIR, VALUES_DIR, APP_DIR] {
+                fs::create_dir_all(dir_path.join(namespace))?;
+            }
+
+            if version == 0 {
+                migrate_flat_layout(&dir_path)?;
+            }
+            if version < SCHEMA_VERSION {
+                write_file(&dir_path, VERSION_FILE, SCHEMA_VERSION.to_string().as_bytes())?;
+            }
+
             Ok(dir_path)
         })
         .await??;
 
-        Ok(FileStorage { dir_path })
+        Ok(FileStorage {
+            dir_path,
+            encryption_key: None,
+        })
+    }
+
+    /// Creates a new `FileStorage` that encrypts the `Config` at rest with a key derived from the given source. A
+    /// plaintext `Config` written without encryption is encrypted the next time it's loaded.
+    pub async fn with_encryption<D: AsRef<OsStr> + ?Sized>(dir: &D, key_source: KeySource) -> Result<Self> {
+        let encryption_key = spawn_blocking(move || key_source.resolve()).await??;
+
+        let mut storage = Self::new(dir).await?;
+        storage.encryption_key = Some(encryption_key);
+
+        Ok(storage)
     }
 
     /// Creates a new `FileStorage` with the current directory as storage path.
@@ -43,14 +130,15 @@ impl FileStorage {
         Self::new(&data_path).await
     }
 
-    fn path_to_file(&self, file: &str) -> PathBuf {
-        let mut file_path = self.dir_path.clone();
-        file_path.push(file);
-        file_path
+    fn path_to_namespace(&self, namespace: &str) -> PathBuf { self.dir_path.join(namespace) }
+
+    fn path_to_data_namespace(&self, namespace: &str) -> Result<PathBuf> {
+        validate_data_key(namespace)?;
+        Ok(self.dir_path.join(APP_DIR).join(namespace))
     }
 
-    async fn get_reader(&self, file: &str) -> Result<BufReader<fs::File>> {
-        let file_path = self.path_to_file(file);
+    async fn get_reader(&self, namespace: &str, file: &str) -> Result<BufReader<fs::File>> {
+        let file_path = self.path_to_namespace(namespace).join(file);
         let reader = spawn_blocking(move || -> Result<BufReader<fs::File>> {
             let file = fs::OpenOptions::new().read(true).open(file_path)?;
             let reader = BufReader::new(file);
@@ -62,21 +150,8 @@ impl FileStorage {
         Ok(reader)
     }
 
-    async fn get_writer(&self, file: &str) -> Result<BufWriter<fs::File>> {
-        let file_path = self.path_to_file(file);
-        let writer = spawn_blocking(move || -> Result<BufWriter<fs::File>> {
-            let file = fs::OpenOptions::new().write(true).create(true).open(file_path)?;
-            let writer = BufWriter::new(file);
-
-            Ok(writer)
-        })
-        .await??;
-
-        Ok(writer)
-    }
-
-    async fn read_bytes(&self, key: &str) -> Result<Vec<u8>> {
-        let mut reader = self.get_reader(key).await?;
+    async fn read_bytes(&self, namespace: &str, key: &str) -> Result<Vec<u8>> {
+        let mut reader = self.get_reader(namespace, key).await?;
         let value = spawn_blocking(move || -> Result<Vec<u8>> {
             let mut value = Vec::new();
             reader.read_to_end(&mut value)?;
@@ -88,10 +163,11 @@ impl FileStorage {
         Ok(value)
     }
 
-    async fn write_bytes(&self, key: &str, value: Vec<u8>) -> Result<()> {
-        let mut writer = self.get_writer(key).await?;
+    async fn write_bytes(&self, namespace: &str, key: &str, value: Vec<u8>) -> Result<()> {
+        let dir_path = self.path_to_namespace(namespace);
+        let key = key.to_string();
         spawn_blocking(move || -> Result<()> {
-            writer.write_all(&value)?;
+            write_file(&dir_path, &key, &value)?;
 
             Ok(())
         })
@@ -100,10 +176,12 @@ impl FileStorage {
         Ok(())
     }
 
-    async fn remove_file(&self, key: &str) -> Result<()> {
-        let file_path = self.path_to_file(key);
+    async fn remove_file(&self, namespace: &str, key: &str) -> Result<()> {
+        let dir_path = self.path_to_namespace(namespace);
+        let file_path = dir_path.join(key);
         spawn_blocking(move || -> Result<()> {
             fs::remove_file(file_path)?;
+            sync_dir(&dir_path)?;
 
             Ok(())
         })
@@ -112,8 +190,24 @@ impl FileStorage {
         Ok(())
     }
 
-    async fn keys_with_suffix(&self, suffix: &'static str) -> Result<Vec<String>> {
-        let dir_path = self.dir_path.clone();
+    /// Removes the directory of a namespace along with everything in it and recreates it empty.
+    async fn clear_namespace(&self, namespace: &str) -> Result<()> {
+        let dir_path = self.path_to_namespace(namespace);
+        let parent_path = self.dir_path.clone();
+        spawn_blocking(move || -> Result<()> {
+            remove_dir_if_exists(&dir_path)?;
+            fs::create_dir_all(&dir_path)?;
+            sync_dir(&parent_path)?;
+
+            Ok(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    async fn keys_with_suffix(&self, namespace: &str, suffix: &'static str) -> Result<Vec<String>> {
+        let dir_path = self.path_to_namespace(namespace);
         let extension = Some(OsStr::new(suffix));
         let keys = spawn_blocking(move || -> Result<Vec<String>> {
             let mut keys = Vec::new();
@@ -139,27 +233,159 @@ impl FileStorage {
     }
 }
 
+/// Writes the value to a temporary file that is synced to disk and then renamed into place, so a crash or a power loss
+/// mid-write leaves either the old or the new file behind, but never a partially written one.
+fn write_file(dir_path: &Path, key: &str, value: &[u8]) -> io::Result<()> {
+    let file_path = dir_path.join(key);
+    let tmp_file_path = dir_path.join(format!("{}.tmp", key));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&tmp_file_path)?;
+    file.write_all(value)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_file_path, &file_path)?;
+    sync_dir(dir_path)
+}
+
+/// Writes the `Config` and the `Pairing`s to the staging directory and swaps them into place. The `Pairing`s are
+/// swapped first, moving the staged `config.json` into place commits the swap.
+fn swap_config_and_pairings(dir_path: &Path, config_bytes: &[u8], pairings: &[(String, Vec<u8>)]) -> io::Result<()> {
+    let staging_path = dir_path.join(STAGING_DIR);
+    let staging_pairings_path = staging_path.join(PAIRINGS_DIR);
+    remove_dir_if_exists(&staging_path)?;
+    fs::create_dir_all(&staging_pairings_path)?;
+    for (key, value) in pairings {
+        write_file(&staging_pairings_path, key, value)?;
+    }
+    write_file(&staging_path, "config.json", config_bytes)?;
+    sync_dir(dir_path)?;
+
+    fs::rename(dir_path.join(PAIRINGS_DIR), dir_path.join(OLD_PAIRINGS_DIR))?;
+    fs::rename(&staging_pairings_path, dir_path.join(PAIRINGS_DIR))?;
+    sync_dir(dir_path)?;
+
+    fs::rename(
+        staging_path.join("config.json"),
+        dir_path.join(CONFIG_DIR).join("config.json"),
+    )?;
+    sync_dir(&dir_path.join(CONFIG_DIR))
+}
+
+/// Completes or rolls back a swap of the `Config` and `Pairing`s that didn't finish and removes the staging directory.
+/// As long as the staged `config.json` is left, the swap isn't committed and the replaced `Pairing`s are restored.
+fn recover_staged_pairings(dir_path: &Path) -> io::Result<()> {
+    let staging_path = dir_path.join(STAGING_DIR);
+    let old_pairings_path = dir_path.join(OLD_PAIRINGS_DIR);
+    if old_pairings_path.exists() {
+        if staging_path.join("config.json").exists() {
+            info!("rolling back an unfinished swap of the config and pairings");
+            remove_dir_if_exists(&dir_path.join(PAIRINGS_DIR))?;
+            fs::rename(&old_pairings_path, dir_path.join(PAIRINGS_DIR))?;
+        } else {
+            fs::remove_dir_all(&old_pairings_path)?;
+        }
+    }
+    remove_dir_if_exists(&staging_path)?;
+
+    sync_dir(dir_path)
+}
+
+fn remove_dir_if_exists(dir_path: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(dir_path) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Moves `config.json` and the pairing files of the flat layout into their namespaces.
+fn migrate_flat_layout(dir_path: &Path) -> io::Result<()> {
+    let mut migrated = false;
+    for entry in fs::read_dir(dir_path)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension() != Some(OsStr::new("json")) {
+            continue;
+        }
+        let stem = match path.file_stem().and_then(OsStr::to_str) {
+            Some(stem) => stem,
+            None => continue,
+        };
+
+        let namespace = if stem == "config" {
+            CONFIG_DIR
+        } else if Uuid::parse_str(stem).is_ok() {
+            PAIRINGS_DIR
+        } else {
+            continue;
+        };
+
+        info!("migrating {} to {}/", path.display(), namespace);
+
+        let file_name = path.file_name().expect("file name of a listed file");
+        fs::rename(&path, dir_path.join(namespace).join(file_name))?;
+        migrated = true;
+    }
+
+    if migrated {
+        for namespace in &[CONFIG_DIR, PAIRINGS_DIR] {
+            sync_dir(&dir_path.join(namespace))?;
+        }
+        sync_dir(dir_path)?;
+    }
+
+    Ok(())
+}
+
+/// Syncs a directory to disk, persisting the renaming or removal of the files in it.
+#[cfg(unix)]
+fn sync_dir(dir_path: &Path) -> io::Result<()> { fs::File::open(dir_path)?.sync_all() }
+
+/// Directories can't be opened as files on non-Unix platforms, so renames are only as durable as the platform makes
+/// them.
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> { Ok(()) }
+
 #[async_trait]
 impl Storage for FileStorage {
     async fn load_config(&self) -> Result<Config> {
-        let config_bytes = self.read_bytes("config.json").await?;
-        let config = serde_json::from_slice(&config_bytes)?;
-        Ok(config)
+        let config_bytes = self.read_bytes(CONFIG_DIR, "config.json").await?;
+        match self.encryption_key {
+            Some(ref key) if encryption::is_sealed(&config_bytes) => {
+                let config_bytes = encryption::open(key, &config_bytes)?;
+                let config = serde_json::from_slice(&config_bytes)?;
+                Ok(config)
+            },
+            Some(ref key) => {
+                let config = serde_json::from_slice(&config_bytes)?;
+                info!("encrypting the plaintext config");
+                let sealed_bytes = encryption::seal(key, &config_bytes)?;
+                self.write_bytes(CONFIG_DIR, "config.json", sealed_bytes).await?;
+                Ok(config)
+            },
+            None => {
+                let config = serde_json::from_slice(&config_bytes)?;
+                Ok(config)
+            },
+        }
     }
 
     async fn save_config(&mut self, config: &Config) -> Result<()> {
-        let config_bytes = serde_json::to_vec(&config)?;
-        self.write_bytes("config.json", config_bytes).await
+        let mut config_bytes = serde_json::to_vec(&config)?;
+        if let Some(ref key) = self.encryption_key {
+            config_bytes = encryption::seal(key, &config_bytes)?;
+        }
+        self.write_bytes(CONFIG_DIR, "config.json", config_bytes).await
     }
 
-    async fn delete_config(&mut self) -> Result<()> {
-        let key = format!("config.json");
-        self.remove_file(&key).await
-    }
+    async fn delete_config(&mut self) -> Result<()> { self.remove_file(CONFIG_DIR, "config.json").await }
 
     async fn load_pairing(&self, id: &Uuid) -> Result<Pairing> {
         let key = format!("{}.json", id.to_string());
-        let pairing_bytes = self.read_bytes(&key).await?;
+        let pairing_bytes = self.read_bytes(PAIRINGS_DIR, &key).await?;
 
         Pairing::from_bytes(&pairing_bytes)
     }
@@ -167,35 +393,307 @@ impl Storage for FileStorage {
     async fn save_pairing(&mut self, pairing: &Pairing) -> Result<()> {
         let key = format!("{}.json", pairing.id.to_string());
         let pairing_bytes = pairing.as_bytes()?;
-        self.write_bytes(&key, pairing_bytes).await
+        self.write_bytes(PAIRINGS_DIR, &key, pairing_bytes).await
     }
 
     async fn delete_pairing(&mut self, id: &Uuid) -> Result<()> {
         let key = format!("{}.json", id.to_string());
-        self.remove_file(&key).await
+        self.remove_file(PAIRINGS_DIR, &key).await
     }
 
     async fn list_pairings(&self) -> Result<Vec<Pairing>> {
         let mut pairings = Vec::new();
-        for key in self.keys_with_suffix("json").await? {
-            if &key != "config" {
-                let pairing_bytes = self.read_bytes(&key).await?;
-                let pairing = Pairing::from_bytes(&pairing_bytes)?;
-                pairings.push(pairing);
-            }
+        for key in self.keys_with_suffix(PAIRINGS_DIR, "json").await? {
+            let pairing_bytes = self.read_bytes(PAIRINGS_DIR, &format!("{}.json", key)).await?;
+            let pairing = Pairing::from_bytes(&pairing_bytes)?;
+            pairings.push(pairing);
         }
 
         Ok(pairings)
     }
 
-    async fn count_pairings(&self) -> Result<usize> {
-        let mut count = 0;
-        for key in self.keys_with_suffix("json").await? {
-            if &key != "device" {
-                count += 1;
+    async fn load_characteristic_value(&self, aid: u64, iid: u64) -> Result<serde_json::Value> {
+        let key = format!("{}.{}.json", aid, iid);
+        let value_bytes = self.read_bytes(VALUES_DIR, &key).await?;
+        let value = serde_json::from_slice(&value_bytes)?;
+        Ok(value)
+    }
+
+    async fn save_characteristic_value(&mut self, aid: u64, iid: u64, value: &serde_json::Value) -> Result<()> {
+        let key = format!("{}.{}.json", aid, iid);
+        let value_bytes = serde_json::to_vec(value)?;
+        self.write_bytes(VALUES_DIR, &key, value_bytes).await
+    }
+
+    async fn delete_characteristic_values(&mut self) -> Result<()> { self.clear_namespace(VALUES_DIR).await }
+
+    async fn load_bytes(&self, namespace: &str, key: &str) -> Result<Vec<u8>> {
+        let file_path = self.path_to_data_namespace(namespace)?.join(key);
+        validate_data_key(key)?;
+        let value = spawn_blocking(move || fs::read(file_path)).await??;
+
+        Ok(value)
+    }
+
+    async fn save_bytes(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        let dir_path = self.path_to_data_namespace(namespace)?;
+        validate_data_key(key)?;
+        let key = key.to_string();
+        let value = value.to_vec();
+        spawn_blocking(move || -> Result<()> {
+            fs::create_dir_all(&dir_path)?;
+            write_file(&dir_path, &key, &value)?;
+
+            Ok(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    async fn delete_bytes(&mut self, namespace: &str, key: &str) -> Result<()> {
+        let dir_path = self.path_to_data_namespace(namespace)?;
+        validate_data_key(key)?;
+        let file_path = dir_path.join(key);
+        spawn_blocking(move || -> Result<()> {
+            fs::remove_file(file_path)?;
+            sync_dir(&dir_path)?;
+
+            Ok(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    async fn list_keys(&self, namespace: &str) -> Result<Vec<String>> {
+        let dir_path = self.path_to_data_namespace(namespace)?;
+        let keys = spawn_blocking(move || -> Result<Vec<String>> {
+            let entries = match fs::read_dir(&dir_path) {
+                Ok(entries) => entries,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+
+            let mut keys = Vec::new();
+            for entry in entries {
+                if let Ok(key) = entry?.file_name().into_string() {
+                    if validate_data_key(&key).is_ok() {
+                        keys.push(key);
+                    }
+                }
             }
+
+            Ok(keys)
+        })
+        .await??;
+
+        Ok(keys)
+    }
+
+    async fn delete_all_bytes(&mut self) -> Result<()> { self.clear_namespace(APP_DIR).await }
+
+    async fn save_config_and_pairings(&mut self, config: &Config, pairings: &[Pairing]) -> Result<()> {
+        let mut config_bytes = serde_json::to_vec(&config)?;
+        if let Some(ref key) = self.encryption_key {
+            config_bytes = encryption::seal(key, &config_bytes)?;
+        }
+        let mut pairing_files = Vec::new();
+        for pairing in pairings {
+            pairing_files.push((format!("{}.json", pairing.id), pairing.as_bytes()?));
         }
 
+        let dir_path = self.dir_path.clone();
+        spawn_blocking(move || -> Result<()> {
+            let result = swap_config_and_pairings(&dir_path, &config_bytes, &pairing_files);
+            // rolls the pairings back if the swap failed before it was committed
+            let cleanup = recover_staged_pairings(&dir_path);
+            result?;
+            cleanup?;
+
+            Ok(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    async fn count_pairings(&self) -> Result<usize> {
+        let count = self.keys_with_suffix(PAIRINGS_DIR, "json").await?.len();
+
         Ok(count)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pairing::Permissions;
+
+    #[tokio::test]
+    async fn test_list_pairings() {
+        let dir = env::temp_dir().join(format!("hap-file-storage-test-{}", Uuid::new_v4()));
+        let mut storage = FileStorage::new(&dir).await.unwrap();
+        storage.save_config(&Config::default()).await.unwrap();
+        assert!(storage.list_pairings().await.unwrap().is_empty());
+
+        let admin = Pairing::new(Uuid::new_v4(), Permissions::Admin, [1; 32]);
+        let user = Pairing::new(Uuid::new_v4(), Permissions::User, [2; 32]);
+        storage.save_pairing(&admin).await.unwrap();
+        storage.save_pairing(&user).await.unwrap();
+
+        let mut pairings = storage.list_pairings().await.unwrap();
+        pairings.sort_by_key(|p| p.public_key);
+        assert_eq!(pairings, vec![admin, user]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_with_shorter_value() {
+        let dir = env::temp_dir().join(format!("hap-file-storage-test-{}", Uuid::new_v4()));
+        let mut storage = FileStorage::new(&dir).await.unwrap();
+
+        let mut config = Config {
+            name: "A Rather Long Accessory Name".into(),
+            ..Default::default()
+        };
+        storage.save_config(&config).await.unwrap();
+        config.name = "Short".into();
+        storage.save_config(&config).await.unwrap();
+        assert_eq!(storage.load_config().await.unwrap().name, "Short");
+
+        let pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [1; 32]);
+        storage.save_pairing(&pairing).await.unwrap();
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![pairing]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_migrate_flat_layout() {
+        let dir = env::temp_dir().join(format!("hap-file-storage-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = Config {
+            name: "Flat".into(),
+            ..Default::default()
+        };
+        fs::write(dir.join("config.json"), serde_json::to_vec(&config).unwrap()).unwrap();
+        let pairing = Pairing::new(Uuid::new_v4(), Permissions::User, [2; 32]);
+        fs::write(dir.join(format!("{}.json", pairing.id)), pairing.as_bytes().unwrap()).unwrap();
+
+        let storage = FileStorage::new(&dir).await.unwrap();
+        assert_eq!(storage.load_config().await.unwrap().name, "Flat");
+        assert_eq!(storage.count_pairings().await.unwrap(), 1);
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![pairing]);
+        assert_eq!(
+            fs::read_to_string(dir.join(VERSION_FILE)).unwrap(),
+            SCHEMA_VERSION.to_string()
+        );
+        assert!(!dir.join("config.json").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_save_config_and_pairings() {
+        let dir = env::temp_dir().join(format!("hap-file-storage-test-{}", Uuid::new_v4()));
+        let mut storage = FileStorage::new(&dir).await.unwrap();
+        let old_pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [1; 32]);
+        storage.save_pairing(&old_pairing).await.unwrap();
+
+        let config = Config {
+            name: "Swapped".into(),
+            ..Default::default()
+        };
+        let new_pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [2; 32]);
+
+        // a directory in place of the config file makes the commit fail, which rolls the pairings back
+        fs::create_dir_all(dir.join(CONFIG_DIR).join("config.json").join("blocker")).unwrap();
+        assert!(storage
+            .save_config_and_pairings(&config, std::slice::from_ref(&new_pairing))
+            .await
+            .is_err());
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![old_pairing.clone()]);
+        assert!(!dir.join(STAGING_DIR).exists());
+        assert!(!dir.join(OLD_PAIRINGS_DIR).exists());
+
+        fs::remove_dir_all(dir.join(CONFIG_DIR).join("config.json")).unwrap();
+        storage
+            .save_config_and_pairings(&config, std::slice::from_ref(&new_pairing))
+            .await
+            .unwrap();
+        assert_eq!(storage.load_config().await.unwrap().name, "Swapped");
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![new_pairing.clone()]);
+
+        // a swap interrupted before the staged config was moved into place is rolled back on the next start
+        fs::rename(dir.join(PAIRINGS_DIR), dir.join(OLD_PAIRINGS_DIR)).unwrap();
+        fs::create_dir_all(dir.join(STAGING_DIR)).unwrap();
+        fs::write(dir.join(STAGING_DIR).join("config.json"), b"{}").unwrap();
+        let storage = FileStorage::new(&dir).await.unwrap();
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![new_pairing]);
+        assert_eq!(storage.load_config().await.unwrap().name, "Swapped");
+        assert!(!dir.join(STAGING_DIR).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_application_data() {
+        let dir = env::temp_dir().join(format!("hap-file-storage-test-{}", Uuid::new_v4()));
+        let mut storage = FileStorage::new(&dir).await.unwrap();
+
+        assert!(storage.list_keys("bridge").await.unwrap().is_empty());
+        storage.save_bytes("bridge", "token", b"secret").await.unwrap();
+        storage.save_bytes("bridge", "mapping", b"{}").await.unwrap();
+        assert_eq!(storage.load_bytes("bridge", "token").await.unwrap(), b"secret");
+
+        let mut keys = storage.list_keys("bridge").await.unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["mapping".to_string(), "token".to_string()]);
+        assert!(dir.join("app").join("bridge").join("mapping").is_file());
+
+        storage.delete_bytes("bridge", "token").await.unwrap();
+        assert!(storage.load_bytes("bridge", "token").await.is_err());
+        assert!(storage.save_bytes("..", "token", b"").await.is_err());
+        assert!(storage.save_bytes("bridge", "a/b", b"").await.is_err());
+
+        storage.delete_all_bytes().await.unwrap();
+        assert!(storage.list_keys("bridge").await.unwrap().is_empty());
+        storage.save_bytes("bridge", "token", b"secret").await.unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_config() {
+        let dir = env::temp_dir().join(format!("hap-file-storage-test-{}", Uuid::new_v4()));
+        let key_source = || KeySource::Callback(Box::new(|| Ok(b"0123456789abcdef0123456789abcdef".to_vec())));
+
+        let mut storage = FileStorage::new(&dir).await.unwrap();
+        let config = Config {
+            name: "Encrypted".into(),
+            ..Default::default()
+        };
+        storage.save_config(&config).await.unwrap();
+
+        // a plaintext config is encrypted on load
+        let storage = FileStorage::with_encryption(&dir, key_source()).await.unwrap();
+        assert_eq!(storage.load_config().await.unwrap().name, "Encrypted");
+        let config_path = dir.join(CONFIG_DIR).join("config.json");
+        assert!(encryption::is_sealed(&fs::read(&config_path).unwrap()));
+
+        let storage = FileStorage::with_encryption(&dir, key_source()).await.unwrap();
+        assert_eq!(storage.load_config().await.unwrap().name, "Encrypted");
+        assert!(FileStorage::new(&dir).await.unwrap().load_config().await.is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&config_path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
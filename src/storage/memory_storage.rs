@@ -0,0 +1,303 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    pairing::Pairing,
+    storage::{storage::validate_data_key, Storage},
+    Config,
+    Error,
+    Result,
+};
+
+/// `MemoryStorage` is an implementor of the `Storage` trait that keeps all data in memory. It's meant for tests and
+/// short-lived setups that shouldn't touch the file system.
+///
+/// Clones of a `MemoryStorage` share the same data, so a clone kept by the application can still be snapshotted after
+/// the original has been handed to an `IpServer`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::storage::MemoryStorage;
+///
+/// let storage = MemoryStorage::new();
+/// let handle = storage.clone();
+/// // `storage` is moved into the server here
+/// let snapshot = handle.snapshot().unwrap();
+/// let restored = MemoryStorage::from_snapshot(snapshot).unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStorage {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    // the config is kept serialized, as `Config` isn't `Clone`
+    config: Option<Vec<u8>>,
+    pairings: HashMap<Uuid, Pairing>,
+    characteristic_values: HashMap<(u64, u64), serde_json::Value>,
+    data: HashMap<String, HashMap<String, Vec<u8>>>,
+}
+
+/// A serializable snapshot of the contents of a `MemoryStorage`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MemoryStorageSnapshot {
+    /// The stored `Config`, if any.
+    pub config: Option<Config>,
+    /// The stored `Pairing`s.
+    pub pairings: Vec<Pairing>,
+    /// The persisted Characteristic values as `(aid, iid, value)`.
+    #[serde(default)]
+    pub characteristic_values: Vec<(u64, u64, serde_json::Value)>,
+    /// The application data by namespace and key.
+    #[serde(default)]
+    pub data: HashMap<String, HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new, empty `MemoryStorage`.
+    pub fn new() -> Self { MemoryStorage::default() }
+
+    /// Creates a new `MemoryStorage` seeded with the contents of a snapshot. Fails if a namespace or key of the
+    /// application data is invalid.
+    pub fn from_snapshot(snapshot: MemoryStorageSnapshot) -> Result<Self> {
+        for (namespace, values) in &snapshot.data {
+            validate_data_key(namespace)?;
+            for key in values.keys() {
+                validate_data_key(key)?;
+            }
+        }
+
+        let config = match snapshot.config {
+            Some(ref config) => Some(serde_json::to_vec(config)?),
+            None => None,
+        };
+        let pairings = snapshot.pairings.into_iter().map(|p| (p.id, p)).collect();
+        let characteristic_values = snapshot
+            .characteristic_values
+            .into_iter()
+            .map(|(aid, iid, value)| ((aid, iid), value))
+            .collect();
+        // empty namespaces aren't kept, the same way `delete_bytes` removes them
+        let data = snapshot
+            .data
+            .into_iter()
+            .filter(|(_, values)| !values.is_empty())
+            .collect();
+
+        Ok(MemoryStorage {
+            inner: Arc::new(Mutex::new(Inner {
+                config,
+                pairings,
+                characteristic_values,
+                data,
+            })),
+        })
+    }
+
+    /// Returns a snapshot of the current contents of the `MemoryStorage`.
+    pub fn snapshot(&self) -> Result<MemoryStorageSnapshot> {
+        let inner = self.lock();
+        let config = match inner.config {
+            Some(ref config_bytes) => Some(serde_json::from_slice(config_bytes)?),
+            None => None,
+        };
+        let pairings = inner.pairings.values().cloned().collect();
+        let characteristic_values = inner
+            .characteristic_values
+            .iter()
+            .map(|(&(aid, iid), value)| (aid, iid, value.clone()))
+            .collect();
+
+        Ok(MemoryStorageSnapshot {
+            config,
+            pairings,
+            characteristic_values,
+            data: inner.data.clone(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // the data is never left half-modified, so it's still usable if another thread panicked while holding the lock
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Returns the same error `FileStorage` returns for missing data.
+fn not_found() -> Error { Error::from(io::Error::from(ErrorKind::NotFound)) }
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn load_config(&self) -> Result<Config> {
+        let config_bytes = self.lock().config.clone().ok_or_else(not_found)?;
+        let config = serde_json::from_slice(&config_bytes)?;
+        Ok(config)
+    }
+
+    async fn save_config(&mut self, config: &Config) -> Result<()> {
+        self.lock().config = Some(serde_json::to_vec(config)?);
+        Ok(())
+    }
+
+    async fn delete_config(&mut self) -> Result<()> {
+        self.lock().config.take().ok_or_else(not_found)?;
+        Ok(())
+    }
+
+    async fn load_pairing(&self, id: &Uuid) -> Result<Pairing> {
+        self.lock().pairings.get(id).cloned().ok_or_else(not_found)
+    }
+
+    async fn save_pairing(&mut self, pairing: &Pairing) -> Result<()> {
+        self.lock().pairings.insert(pairing.id, pairing.clone());
+        Ok(())
+    }
+
+    async fn delete_pairing(&mut self, id: &Uuid) -> Result<()> {
+        self.lock().pairings.remove(id).ok_or_else(not_found)?;
+        Ok(())
+    }
+
+    async fn list_pairings(&self) -> Result<Vec<Pairing>> { Ok(self.lock().pairings.values().cloned().collect()) }
+
+    async fn count_pairings(&self) -> Result<usize> { Ok(self.lock().pairings.len()) }
+
+    async fn save_config_and_pairings(&mut self, config: &Config, pairings: &[Pairing]) -> Result<()> {
+        let config_bytes = serde_json::to_vec(config)?;
+        let mut inner = self.lock();
+        inner.config = Some(config_bytes);
+        inner.pairings = pairings.iter().map(|p| (p.id, p.clone())).collect();
+        Ok(())
+    }
+
+    async fn load_characteristic_value(&self, aid: u64, iid: u64) -> Result<serde_json::Value> {
+        self.lock()
+            .characteristic_values
+            .get(&(aid, iid))
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn save_characteristic_value(&mut self, aid: u64, iid: u64, value: &serde_json::Value) -> Result<()> {
+        self.lock().characteristic_values.insert((aid, iid), value.clone());
+        Ok(())
+    }
+
+    async fn delete_characteristic_values(&mut self) -> Result<()> {
+        self.lock().characteristic_values.clear();
+        Ok(())
+    }
+
+    async fn load_bytes(&self, namespace: &str, key: &str) -> Result<Vec<u8>> {
+        validate_data_key(namespace)?;
+        validate_data_key(key)?;
+        self.lock()
+            .data
+            .get(namespace)
+            .and_then(|values| values.get(key))
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn save_bytes(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        validate_data_key(namespace)?;
+        validate_data_key(key)?;
+        self.lock()
+            .data
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    async fn delete_bytes(&mut self, namespace: &str, key: &str) -> Result<()> {
+        validate_data_key(namespace)?;
+        validate_data_key(key)?;
+        let mut inner = self.lock();
+        let values = inner.data.get_mut(namespace).ok_or_else(not_found)?;
+        values.remove(key).ok_or_else(not_found)?;
+        if values.is_empty() {
+            inner.data.remove(namespace);
+        }
+        Ok(())
+    }
+
+    async fn list_keys(&self, namespace: &str) -> Result<Vec<String>> {
+        validate_data_key(namespace)?;
+        Ok(self
+            .lock()
+            .data
+            .get(namespace)
+            .map(|values| values.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn delete_all_bytes(&mut self) -> Result<()> {
+        self.lock().data.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::pairing::Permissions;
+
+    #[tokio::test]
+    async fn test_snapshot_roundtrip() {
+        let mut storage = MemoryStorage::new();
+        assert!(storage.load_config().await.is_err());
+
+        let config = Config {
+            name: "Memory".into(),
+            ..Default::default()
+        };
+        storage.save_config(&config).await.unwrap();
+        let pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [3; 32]);
+        storage.save_pairing(&pairing).await.unwrap();
+        storage.save_characteristic_value(1, 9, &json!(true)).await.unwrap();
+        storage.save_bytes("bridge", "token", b"secret").await.unwrap();
+
+        let snapshot = serde_json::to_vec(&storage.snapshot().unwrap()).unwrap();
+        let restored = MemoryStorage::from_snapshot(serde_json::from_slice(&snapshot).unwrap()).unwrap();
+
+        assert_eq!(restored.load_config().await.unwrap().name, "Memory");
+        assert_eq!(restored.load_pairing(&pairing.id).await.unwrap(), pairing);
+        assert_eq!(restored.count_pairings().await.unwrap(), 1);
+        assert_eq!(restored.load_characteristic_value(1, 9).await.unwrap(), json!(true));
+        assert_eq!(restored.load_bytes("bridge", "token").await.unwrap(), b"secret");
+    }
+
+    #[tokio::test]
+    async fn test_shared_clones() {
+        let storage = MemoryStorage::new();
+        let mut server_storage: Box<dyn Storage> = Box::new(storage.clone());
+        server_storage.save_bytes("bridge", "token", b"secret").await.unwrap();
+
+        let snapshot = storage.snapshot().unwrap();
+        assert_eq!(snapshot.data["bridge"]["token"], b"secret");
+
+        assert!(server_storage.save_bytes("bridge", "../token", b"").await.is_err());
+    }
+
+    #[test]
+    fn test_snapshot_invalid_key() {
+        let mut snapshot = MemoryStorageSnapshot::default();
+        snapshot
+            .data
+            .entry("bridge".into())
+            .or_default()
+            .insert(".hidden".into(), Vec::new());
+        assert!(MemoryStorage::from_snapshot(snapshot).is_err());
+    }
+}
@@ -1,6 +1,20 @@
+mod backup;
+mod encryption;
 mod file_storage;
+mod memory_storage;
+#[cfg(feature = "sled-storage")]
+mod sled_storage;
 mod storage;
 
 pub(crate) mod accessory_list;
 
-pub use self::{file_storage::FileStorage, storage::Storage};
+pub use self::{
+    backup::{export_backup, import_backup, BackupAuthentication},
+    encryption::KeySource,
+    file_storage::FileStorage,
+    memory_storage::{MemoryStorage, MemoryStorageSnapshot},
+    storage::Storage,
+};
+
+#[cfg(feature = "sled-storage")]
+pub use self::sled_storage::SledStorage;
@@ -0,0 +1,305 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Transactional,
+};
+use tokio::task::spawn_blocking;
+use uuid::Uuid;
+
+use crate::{
+    pairing::Pairing,
+    storage::{storage::validate_data_key, Storage},
+    Config,
+    Error,
+    Result,
+};
+
+const CONFIG_KEY: &[u8] = b"config";
+
+/// `SledStorage` is an implementor of the `Storage` trait that stores data in an embedded [sled](https://sled.rs)
+/// database. It's available with the `sled-storage` feature.
+///
+/// The `Config`, the `Pairing`s, persisted Characteristic values and additional data are kept in separate trees of the
+/// database. Pairings are indexed by their ID, and every write is flushed to disk before it's reported as done. All
+/// database calls run on the blocking thread pool. `save_config_and_pairings` runs as a single transaction.
+#[derive(Debug, Clone)]
+pub struct SledStorage {
+    db: sled::Db,
+    config: sled::Tree,
+    pairings: sled::Tree,
+    values: sled::Tree,
+    data: sled::Tree,
+}
+
+impl SledStorage {
+    /// Creates a new `SledStorage`, opening or creating the database at the given path.
+    pub async fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let db = spawn_blocking(move || sled::open(path)).await??;
+
+        Self::from_db(db)
+    }
+
+    /// Creates a new `SledStorage` on top of an already opened database.
+    pub fn from_db(db: sled::Db) -> Result<Self> {
+        let config = db.open_tree("config")?;
+        let pairings = db.open_tree("pairings")?;
+        let values = db.open_tree("values")?;
+        let data = db.open_tree("data")?;
+
+        Ok(SledStorage {
+            db,
+            config,
+            pairings,
+            values,
+            data,
+        })
+    }
+
+    /// Runs a write on the blocking thread pool and flushes the database afterwards, so sled's disk I/O doesn't stall
+    /// the executor.
+    async fn write<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        let db = self.db.clone();
+        spawn_blocking(move || -> Result<()> {
+            f()?;
+            db.flush()?;
+
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// Runs a read on the blocking thread pool.
+async fn read<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    spawn_blocking(f).await?
+}
+
+/// Concatenates the big-endian Accessory and instance IDs.
+fn value_key(aid: u64, iid: u64) -> Vec<u8> { [aid.to_be_bytes(), iid.to_be_bytes()].concat() }
+
+/// Prefixes the key with its namespace and a null byte, so namespaces that are prefixes of one another don't overlap.
+fn data_key(namespace: &str, key: &str) -> Result<Vec<u8>> {
+    validate_data_key(namespace)?;
+    validate_data_key(key)?;
+
+    Ok([namespace.as_bytes(), &[0], key.as_bytes()].concat())
+}
+
+/// Returns the same error `FileStorage` returns for missing data.
+fn not_found() -> Error { Error::from(std::io::Error::from(std::io::ErrorKind::NotFound)) }
+
+#[async_trait]
+impl Storage for SledStorage {
+    async fn load_config(&self) -> Result<Config> {
+        let tree = self.config.clone();
+        let config_bytes = read(move || Ok(tree.get(CONFIG_KEY)?)).await?.ok_or_else(not_found)?;
+        let config = serde_json::from_slice(&config_bytes)?;
+        Ok(config)
+    }
+
+    async fn save_config(&mut self, config: &Config) -> Result<()> {
+        let config_bytes = serde_json::to_vec(config)?;
+        let tree = self.config.clone();
+        self.write(move || {
+            tree.insert(CONFIG_KEY, config_bytes)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_config(&mut self) -> Result<()> {
+        let tree = self.config.clone();
+        self.write(move || {
+            tree.remove(CONFIG_KEY)?.ok_or_else(not_found)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_pairing(&self, id: &Uuid) -> Result<Pairing> {
+        let (tree, id) = (self.pairings.clone(), *id);
+        let pairing_bytes = read(move || Ok(tree.get(id.as_bytes())?))
+            .await?
+            .ok_or_else(not_found)?;
+        Pairing::from_bytes(&pairing_bytes)
+    }
+
+    async fn save_pairing(&mut self, pairing: &Pairing) -> Result<()> {
+        let (tree, id, pairing_bytes) = (self.pairings.clone(), pairing.id, pairing.as_bytes()?);
+        self.write(move || {
+            tree.insert(id.as_bytes(), pairing_bytes)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_pairing(&mut self, id: &Uuid) -> Result<()> {
+        let (tree, id) = (self.pairings.clone(), *id);
+        self.write(move || {
+            tree.remove(id.as_bytes())?.ok_or_else(not_found)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_pairings(&self) -> Result<Vec<Pairing>> {
+        let tree = self.pairings.clone();
+        read(move || {
+            let mut pairings = Vec::new();
+            for value in tree.iter().values() {
+                pairings.push(Pairing::from_bytes(&value?)?);
+            }
+
+            Ok(pairings)
+        })
+        .await
+    }
+
+    async fn count_pairings(&self) -> Result<usize> {
+        let tree = self.pairings.clone();
+        read(move || Ok(tree.len())).await
+    }
+
+    async fn save_config_and_pairings(&mut self, config: &Config, pairings: &[Pairing]) -> Result<()> {
+        let config_bytes = serde_json::to_vec(config)?;
+        let mut pairing_entries = Vec::new();
+        for pairing in pairings {
+            pairing_entries.push((pairing.id.as_bytes().to_vec(), pairing.as_bytes()?));
+        }
+
+        let (config, pairings) = (self.config.clone(), self.pairings.clone());
+        self.write(move || {
+            let stale_keys = pairings.iter().keys().collect::<std::result::Result<Vec<_>, _>>()?;
+
+            (&config, &pairings)
+                .transaction(|(config_tree, pairings_tree)| {
+                    config_tree.insert(CONFIG_KEY, config_bytes.as_slice())?;
+                    for key in &stale_keys {
+                        pairings_tree.remove(key)?;
+                    }
+                    for (key, value) in &pairing_entries {
+                        pairings_tree.insert(key.as_slice(), value.as_slice())?;
+                    }
+
+                    Ok::<_, ConflictableTransactionError<sled::Error>>(())
+                })
+                .map_err(|e| match e {
+                    TransactionError::Abort(e) | TransactionError::Storage(e) => Error::from(e),
+                })
+        })
+        .await
+    }
+
+    async fn load_characteristic_value(&self, aid: u64, iid: u64) -> Result<serde_json::Value> {
+        let tree = self.values.clone();
+        let value_bytes = read(move || Ok(tree.get(value_key(aid, iid))?))
+            .await?
+            .ok_or_else(not_found)?;
+        let value = serde_json::from_slice(&value_bytes)?;
+        Ok(value)
+    }
+
+    async fn save_characteristic_value(&mut self, aid: u64, iid: u64, value: &serde_json::Value) -> Result<()> {
+        let (tree, value_bytes) = (self.values.clone(), serde_json::to_vec(value)?);
+        self.write(move || {
+            tree.insert(value_key(aid, iid), value_bytes)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_characteristic_values(&mut self) -> Result<()> {
+        let tree = self.values.clone();
+        self.write(move || Ok(tree.clear()?)).await
+    }
+
+    async fn load_bytes(&self, namespace: &str, key: &str) -> Result<Vec<u8>> {
+        let (tree, key) = (self.data.clone(), data_key(namespace, key)?);
+        read(move || Ok(tree.get(key)?))
+            .await?
+            .map(|value| value.to_vec())
+            .ok_or_else(not_found)
+    }
+
+    async fn save_bytes(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()> {
+        let (tree, key, value) = (self.data.clone(), data_key(namespace, key)?, value.to_vec());
+        self.write(move || {
+            tree.insert(key, value)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_bytes(&mut self, namespace: &str, key: &str) -> Result<()> {
+        let (tree, key) = (self.data.clone(), data_key(namespace, key)?);
+        self.write(move || {
+            tree.remove(key)?.ok_or_else(not_found)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_keys(&self, namespace: &str) -> Result<Vec<String>> {
+        validate_data_key(namespace)?;
+        let (tree, prefix) = (self.data.clone(), [namespace.as_bytes(), &[0]].concat());
+        read(move || {
+            let mut keys = Vec::new();
+            for key in tree.scan_prefix(&prefix).keys() {
+                let key = key?;
+                keys.push(String::from_utf8_lossy(&key[prefix.len()..]).into_owned());
+            }
+
+            Ok(keys)
+        })
+        .await
+    }
+
+    async fn delete_all_bytes(&mut self) -> Result<()> {
+        let tree = self.data.clone();
+        self.write(move || Ok(tree.clear()?)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pairing::Permissions;
+
+    #[tokio::test]
+    async fn test_save_config_and_pairings() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut storage = SledStorage::from_db(db).unwrap();
+
+        let old_pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [4; 32]);
+        storage.save_pairing(&old_pairing).await.unwrap();
+
+        let config = Config {
+            name: "Sled".into(),
+            ..Default::default()
+        };
+        let new_pairing = Pairing::new(Uuid::new_v4(), Permissions::Admin, [5; 32]);
+        storage
+            .save_config_and_pairings(&config, std::slice::from_ref(&new_pairing))
+            .await
+            .unwrap();
+
+        assert_eq!(storage.load_config().await.unwrap().name, "Sled");
+        assert_eq!(storage.list_pairings().await.unwrap(), vec![new_pairing]);
+        assert!(storage.load_pairing(&old_pairing.id).await.is_err());
+
+        storage.save_bytes("bridge", "token", b"secret").await.unwrap();
+        storage.save_bytes("bridgeX", "other", b"").await.unwrap();
+        assert_eq!(storage.load_bytes("bridge", "token").await.unwrap(), b"secret");
+        assert_eq!(storage.list_keys("bridge").await.unwrap(), vec!["token".to_string()]);
+    }
+}
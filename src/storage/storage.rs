@@ -1,9 +1,14 @@
 use async_trait::async_trait;
 use uuid::Uuid;
 
-use crate::{pairing::Pairing, Config, Result};
+use crate::{pairing::Pairing, Config, Error, Result};
 
-/// `Storage` is implemented by the data storage methods HAP supports. Currently, that's just `FileStorage`.
+/// `Storage` is implemented by the data storage methods HAP supports. Currently, that's `FileStorage`,
+/// `MemoryStorage` and, with the `sled-storage` feature, `SledStorage`.
+///
+/// Besides the data of the accessory server itself, a `Storage` holds application data as byte values in named
+/// namespaces, so an application can keep all of its state in one place. Namespaces and keys have to be non-empty and
+/// must not contain path separators, null bytes or start with a dot.
 #[async_trait]
 pub trait Storage: Send + Sync {
     /// Loads the `Config` from the `Storage`.
@@ -22,4 +27,33 @@ pub trait Storage: Send + Sync {
     async fn list_pairings(&self) -> Result<Vec<Pairing>>;
     /// Selects the count of stored `Pairing`s from the `Storage`.
     async fn count_pairings(&self) -> Result<usize>;
+    /// Saves the `Config` and replaces all stored `Pairing`s with the given ones at once, so either all of the changes
+    /// are persisted or none of them.
+    async fn save_config_and_pairings(&mut self, config: &Config, pairings: &[Pairing]) -> Result<()>;
+    /// Loads the persisted value of the Characteristic with the given Accessory and instance IDs from the `Storage`.
+    async fn load_characteristic_value(&self, aid: u64, iid: u64) -> Result<serde_json::Value>;
+    /// Saves the value of the Characteristic with the given Accessory and instance IDs into the `Storage`.
+    async fn save_characteristic_value(&mut self, aid: u64, iid: u64, value: &serde_json::Value) -> Result<()>;
+    /// Deletes all persisted Characteristic values from the `Storage`.
+    async fn delete_characteristic_values(&mut self) -> Result<()>;
+    /// Loads a value of application data from the given namespace of the `Storage`.
+    async fn load_bytes(&self, namespace: &str, key: &str) -> Result<Vec<u8>>;
+    /// Saves a value of application data into the given namespace of the `Storage`.
+    async fn save_bytes(&mut self, namespace: &str, key: &str, value: &[u8]) -> Result<()>;
+    /// Deletes a value of application data from the given namespace of the `Storage`.
+    async fn delete_bytes(&mut self, namespace: &str, key: &str) -> Result<()>;
+    /// Lists the keys of all values of application data in the given namespace of the `Storage`.
+    async fn list_keys(&self, namespace: &str) -> Result<Vec<String>>;
+    /// Deletes all application data from all namespaces of the `Storage`.
+    async fn delete_all_bytes(&mut self) -> Result<()>;
+}
+
+/// Checks that a namespace or key of application data follows the rules of the `Storage` trait, which keep it usable
+/// as a file name.
+pub(crate) fn validate_data_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.starts_with('.') || key.ends_with(".tmp") || key.contains(&['/', '\\', '\0'][..]) {
+        return Err(Error::InvalidStorageKey(key.to_string()));
+    }
+
+    Ok(())
 }
@@ -180,7 +180,10 @@ pub enum Error {
 impl From<error::Error> for Error {
     fn from(err: error::Error) -> Self {
         error!("{:?}", err);
-        Error::Unknown
+        match err {
+            error::Error::MaxPeers => Error::MaxPeers,
+            _ => Error::Unknown,
+        }
     }
 }
 
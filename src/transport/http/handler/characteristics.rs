@@ -8,6 +8,7 @@ use hyper::{Body, Response, StatusCode, Uri};
 use url::form_urlencoded;
 
 use crate::{
+    characteristic::{CallbackContext, ChangeOrigin},
     pointer,
     transport::http::{
         handler::JsonHandlerExt,
@@ -34,10 +35,10 @@ impl JsonHandlerExt for GetCharacteristics {
         &mut self,
         uri: Uri,
         _: Body,
-        _: pointer::ControllerId,
+        controller_id: pointer::ControllerId,
         _: pointer::EventSubscriptions,
         _: pointer::Config,
-        _: pointer::Storage,
+        storage: pointer::Storage,
         accessory_list: pointer::AccessoryList,
         _: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
@@ -55,6 +56,7 @@ impl JsonHandlerExt for GetCharacteristics {
                 let (f_meta, f_perms, f_type, f_ev) = check_flags(&queries);
                 let q_id = queries.get("id").ok_or(Error::HttpStatus(StatusCode::BAD_REQUEST))?;
                 let ids = q_id.split(',').collect::<Vec<&str>>();
+                let context = callback_context(&controller_id, &storage).await;
                 for id in ids {
                     let id_pair = id.split('.').collect::<Vec<&str>>();
                     if id_pair.len() != 2 {
@@ -66,7 +68,7 @@ impl JsonHandlerExt for GetCharacteristics {
                     let res_object = match accessory_list
                         .lock()
                         .await
-                        .read_characteristic(aid, iid, f_meta, f_perms, f_type, f_ev)
+                        .read_characteristic(aid, iid, f_meta, f_perms, f_type, f_ev, &context)
                         .await
                     {
                         Ok(mut res_object) => {
@@ -76,12 +78,12 @@ impl JsonHandlerExt for GetCharacteristics {
                             }
                             res_object
                         },
-                        Err(_) => {
+                        Err(e) => {
                             some_err = true;
                             ReadResponseObject {
                                 iid,
                                 aid,
-                                status: Some(Status::ServiceCommunicationFailure as i32),
+                                status: Some(Status::from(&e) as i32),
                                 ..Default::default()
                             }
                         },
@@ -118,6 +120,22 @@ fn check_flags(flags: &HashMap<String, String>) -> (bool, bool, bool, bool) {
     )
 }
 
+/// Returns the context of a request of the verified controller of the session, passed to the contextual callbacks
+/// of the Characteristics.
+async fn callback_context(controller_id: &pointer::ControllerId, storage: &pointer::Storage) -> CallbackContext {
+    let controller_id = *controller_id.read().expect("reading controller_id");
+    let permissions = match controller_id {
+        Some(ref id) => storage.lock().await.load_pairing(id).await.ok().map(|p| p.permissions),
+        None => None,
+    };
+
+    CallbackContext {
+        controller_id,
+        permissions,
+        ..CallbackContext::with_origin(ChangeOrigin::Controller)
+    }
+}
+
 pub struct UpdateCharacteristics;
 
 impl UpdateCharacteristics {
@@ -129,10 +147,10 @@ impl JsonHandlerExt for UpdateCharacteristics {
         &mut self,
         _: Uri,
         body: Body,
-        _: pointer::ControllerId,
+        controller_id: pointer::ControllerId,
         event_subscriptions: pointer::EventSubscriptions,
         _: pointer::Config,
-        _: pointer::Storage,
+        storage: pointer::Storage,
         accessories: pointer::AccessoryList,
         _: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
@@ -150,6 +168,7 @@ impl JsonHandlerExt for UpdateCharacteristics {
             };
             let mut some_err = false;
             let mut all_err = true;
+            let context = callback_context(&controller_id, &storage).await;
 
             for c in write_body.characteristics {
                 let iid = c.iid;
@@ -157,7 +176,7 @@ impl JsonHandlerExt for UpdateCharacteristics {
                 let res_object = match accessories
                     .lock()
                     .await
-                    .write_characteristic(c, &event_subscriptions)
+                    .write_characteristic(c, &event_subscriptions, &context)
                     .await
                 {
                     Ok(res_object) => {
@@ -168,12 +187,12 @@ impl JsonHandlerExt for UpdateCharacteristics {
                         }
                         res_object
                     },
-                    Err(_) => {
+                    Err(e) => {
                         some_err = true;
                         WriteResponseObject {
                             iid,
                             aid,
-                            status: Status::ServiceCommunicationFailure as i32,
+                            status: Status::from(&e) as i32,
                         }
                     },
                 };
@@ -194,3 +213,81 @@ impl JsonHandlerExt for UpdateCharacteristics {
         .boxed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use futures::lock::Mutex;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        accessory::{lightbulb::LightbulbAccessory, AccessoryInformation},
+        characteristic::{CallbackError, ContextCharacteristicCallbacks, HapCharacteristic},
+        event::EventEmitter,
+        storage::{accessory_list::AccessoryList, MemoryStorage},
+        Config,
+    };
+
+    #[tokio::test]
+    async fn test_rejected_write_status() {
+        let event_emitter = Arc::new(Mutex::new(EventEmitter::new()));
+        let accessory_list = Arc::new(Mutex::new(AccessoryList::new(event_emitter.clone())));
+        let errors = [
+            CallbackError::ServiceCommunicationFailure,
+            CallbackError::ResourceBusy,
+            CallbackError::InvalidValueInRequest,
+        ];
+        let mut lightbulbs = Vec::new();
+        for (aid, error) in (1..).zip(errors.iter().copied()) {
+            let mut lightbulb = LightbulbAccessory::new(aid, AccessoryInformation::default()).unwrap();
+            lightbulb
+                .lightbulb
+                .on
+                .on_update_with_context(Some(move |_, _, _| async move { Err(error) }.boxed()));
+            let lightbulb = Arc::new(Mutex::new(lightbulb));
+            accessory_list
+                .lock()
+                .await
+                .add_accessory(lightbulb.clone())
+                .await
+                .unwrap();
+            lightbulbs.push(lightbulb);
+        }
+        let iid = lightbulbs[0].lock().await.lightbulb.on.get_id();
+
+        let body = json!({
+            "characteristics": (1..=3).map(|aid| json!({"aid": aid, "iid": iid, "value": true})).collect::<Vec<_>>(),
+        });
+        let storage: Box<dyn crate::storage::Storage> = Box::new(MemoryStorage::new());
+        let response = UpdateCharacteristics::new()
+            .handle(
+                Uri::from_static("/characteristics"),
+                Body::from(body.to_string()),
+                Arc::new(RwLock::new(None)),
+                Arc::new(Mutex::new(Vec::new())),
+                Arc::new(Mutex::new(Config::default())),
+                Arc::new(Mutex::new(storage)),
+                accessory_list,
+                event_emitter,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let statuses = body["characteristics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["status"].as_i64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![-70402, -70403, -70410]);
+
+        for lightbulb in lightbulbs {
+            assert_eq!(lightbulb.lock().await.lightbulb.on.get_value().await.unwrap(), false);
+        }
+    }
+}
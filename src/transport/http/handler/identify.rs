@@ -3,6 +3,8 @@ use hyper::{Body, Response, StatusCode, Uri};
 use serde_json::json;
 
 use crate::{
+    characteristic::ChangeOrigin,
+    event::Event,
     pointer,
     transport::http::{handler::JsonHandlerExt, json_response, status_response, Status},
     HapType,
@@ -25,7 +27,7 @@ impl JsonHandlerExt for Identify {
         _: pointer::Config,
         storage: pointer::Storage,
         accessory_list: pointer::AccessoryList,
-        _: pointer::EventEmitter,
+        event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<Response<Body>>> {
         let storage = storage.clone();
         let accessory_list = accessory_list.clone();
@@ -37,15 +39,18 @@ impl JsonHandlerExt for Identify {
             }
 
             for accessory in accessory_list.lock().await.accessories.iter_mut() {
-                accessory
-                    .lock()
-                    .await
-                    .get_mut_service(HapType::AccessoryInformation)
+                let mut a = accessory.lock().await;
+                a.get_mut_service(HapType::AccessoryInformation)
                     .expect("missing Accessory Information Service") // every accessory needs to have it, so this should never panic
                     .get_mut_characteristic(HapType::Identify)
                     .expect("missing Identify Characteristic on Accessory Information Service")
-                    .set_value(serde_json::Value::Bool(true))
+                    .set_value_with_origin(serde_json::Value::Bool(true), ChangeOrigin::Controller)
                     .await?;
+
+                event_emitter
+                    .lock()
+                    .await
+                    .emit(&Event::IdentifyRequested { aid: a.get_id() });
             }
 
             // TODO: defer setting them all back to false after a few secs
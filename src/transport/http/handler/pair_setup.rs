@@ -7,14 +7,13 @@ use futures::{
     stream::StreamExt,
 };
 use hyper::Body;
-use log::{debug, info};
+use log::{debug, error, info};
 use num::BigUint;
 use rand::{rngs::OsRng, RngCore};
 use ring::{digest, hkdf, hmac};
 use sha2::{Digest, Sha512};
 use signature::{Signature, Signer, Verifier};
 use srp::{
-    client::{srp_private_key, SrpClient},
     groups::G_3072,
     server::{SrpServer, UserRecord},
     types::SrpGroup,
@@ -27,6 +26,7 @@ use crate::{
     pointer,
     tlv::{self, Encodable, Type, Value},
     transport::http::handler::TlvHandlerExt,
+    SetupVerifier,
 };
 
 struct Session {
@@ -128,36 +128,27 @@ impl TlvHandlerExt for PairSetup {
         event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<tlv::Container, tlv::ErrorContainer>> {
         async move {
-            match step {
-                Step::Start => match handle_start(self, config).await {
-                    Ok(res) => {
-                        self.unsuccessful_tries = 0;
-                        Ok(res)
-                    },
-                    Err(err) => {
-                        self.unsuccessful_tries += 1;
-                        Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, err))
-                    },
-                },
-                Step::Verify { a_pub, a_proof } => match handle_verify(self, &a_pub, &a_proof).await {
-                    Ok(res) => {
-                        self.unsuccessful_tries = 0;
-                        Ok(res)
-                    },
-                    Err(err) => {
-                        self.unsuccessful_tries += 1;
-                        Err(tlv::ErrorContainer::new(StepNumber::VerifyRes as u8, err))
-                    },
+            let (step_number, res) = match step {
+                Step::Start => (StepNumber::StartRes, handle_start(self, config).await),
+                Step::Verify { a_pub, a_proof } => (StepNumber::VerifyRes, handle_verify(self, &a_pub, &a_proof).await),
+                Step::Exchange { data } => (
+                    StepNumber::ExchangeRes,
+                    handle_exchange(self, config, storage, event_emitter.clone(), &data).await,
+                ),
+            };
+
+            match res {
+                Ok(res) => {
+                    self.unsuccessful_tries = 0;
+                    Ok(res)
                 },
-                Step::Exchange { data } => match handle_exchange(self, config, storage, event_emitter, &data).await {
-                    Ok(res) => {
-                        self.unsuccessful_tries = 0;
-                        Ok(res)
-                    },
-                    Err(err) => {
-                        self.unsuccessful_tries += 1;
-                        Err(tlv::ErrorContainer::new(StepNumber::ExchangeRes as u8, err))
-                    },
+                Err(err) => {
+                    self.unsuccessful_tries += 1;
+                    event_emitter
+                        .lock()
+                        .await
+                        .emit(&Event::PairSetupFailed { reason: err.into() });
+                    Err(tlv::ErrorContainer::new(step_number as u8, err))
                 },
             }
         }
@@ -176,16 +167,15 @@ async fn handle_start(handler: &mut PairSetup, config: pointer::Config) -> Resul
     // let salt = rng.sample_iter::<u8, Standard>(Standard).take(16).collect::<Vec<u8>>(); // s
     // let b = rng.sample_iter::<u8, Standard>(Standard).take(64).collect::<Vec<u8>>();
 
+    let SetupVerifier { salt, verifier } = config.lock().await.current_setup_verifier().ok_or_else(|| {
+        error!("neither a pin nor a setup verifier is configured");
+        tlv::Error::Unknown
+    })?; // s, v
+
     let mut csprng = OsRng {};
-    let mut salt = [0; 16]; // s
     let mut b = [0; 64];
-    csprng.fill_bytes(&mut salt);
     csprng.fill_bytes(&mut b);
 
-    let private_key = srp_private_key::<Sha512>(b"Pair-Setup", &config.lock().await.pin.to_string().as_bytes(), &salt); // x = H(s | H(I | ":" | P))
-    let srp_client = SrpClient::<Sha512>::new(&private_key, &G_3072);
-    let verifier = srp_client.get_password_verifier(&private_key); // v = g^x
-
     let user = UserRecord {
         username: b"Pair-Setup",
         salt: &salt,
@@ -349,8 +339,7 @@ async fn handle_exchange(
                 event_emitter
                     .lock()
                     .await
-                    .emit(&Event::ControllerPaired { id: pairing.id })
-                    .await;
+                    .emit(&Event::ControllerPaired { id: pairing.id });
 
                 info!("pair setup M6: sending SRP exchange response");
 
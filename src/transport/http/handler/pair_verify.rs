@@ -1,4 +1,4 @@
-use std::str;
+use std::{net::SocketAddr, str};
 
 use aead::{generic_array::GenericArray, AeadInPlace, NewAead};
 use chacha20poly1305::ChaCha20Poly1305;
@@ -16,6 +16,7 @@ use uuid::Uuid;
 use x25519_dalek::{EphemeralSecret, PublicKey};
 
 use crate::{
+    event::Event,
     pointer,
     tlv::{self, Encodable, Type, Value},
     transport::{http::handler::TlvHandlerExt, tcp},
@@ -31,13 +32,15 @@ struct Session {
 pub struct PairVerify {
     session: Option<Session>,
     session_sender: Option<oneshot::Sender<tcp::Session>>,
+    peer_addr: SocketAddr,
 }
 
 impl PairVerify {
-    pub fn new(session_sender: oneshot::Sender<tcp::Session>) -> PairVerify {
+    pub fn new(session_sender: oneshot::Sender<tcp::Session>, peer_addr: SocketAddr) -> PairVerify {
         PairVerify {
             session: None,
             session_sender: Some(session_sender),
+            peer_addr,
         }
     }
 }
@@ -108,7 +111,7 @@ impl TlvHandlerExt for PairVerify {
         _: pointer::ControllerId,
         config: pointer::Config,
         storage: pointer::Storage,
-        _: pointer::EventEmitter,
+        event_emitter: pointer::EventEmitter,
     ) -> BoxFuture<Result<tlv::Container, tlv::ErrorContainer>> {
         async move {
             match step {
@@ -116,7 +119,7 @@ impl TlvHandlerExt for PairVerify {
                     Ok(res) => Ok(res),
                     Err(err) => Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, err)),
                 },
-                Step::Finish { data } => match handle_finish(self, storage, &data).await {
+                Step::Finish { data } => match handle_finish(self, storage, event_emitter, &data).await {
                     Ok(res) => Ok(res),
                     Err(err) => Err(tlv::ErrorContainer::new(StepNumber::FinishRes as u8, err)),
                 },
@@ -203,6 +206,7 @@ async fn handle_start(
 async fn handle_finish(
     handler: &mut PairVerify,
     storage: pointer::Storage,
+    event_emitter: pointer::EventEmitter,
     data: &[u8],
 ) -> Result<tlv::Container, tlv::Error> {
     info!("pair verify M3: received verify finish request");
@@ -267,6 +271,11 @@ async fn handle_finish(
                 return Err(tlv::Error::Unknown);
             }
 
+            event_emitter.lock().await.emit(&Event::SessionVerified {
+                controller_id: pairing_uuid,
+                peer_addr: handler.peer_addr,
+            });
+
             info!("pair verify M4: sending verify finish response");
 
             Ok(vec![Value::State(StepNumber::FinishRes as u8)])
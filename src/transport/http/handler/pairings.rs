@@ -9,8 +9,7 @@ use log::{debug, info};
 use uuid::Uuid;
 
 use crate::{
-    event::Event,
-    pairing::{Pairing, Permissions},
+    pairing::{self, Permissions},
     pointer,
     tlv::{self, Type, Value},
     transport::http::handler::TlvHandlerExt,
@@ -162,50 +161,7 @@ async fn handle_add(
     let uuid_str = str::from_utf8(&pairing_id)?;
     let pairing_uuid = Uuid::parse_str(uuid_str)?;
 
-    let mut s = storage.lock().await;
-    match s.load_pairing(&pairing_uuid).await {
-        Ok(mut pairing) => {
-            if ed25519_dalek::PublicKey::from_bytes(&pairing.public_key)?
-                != ed25519_dalek::PublicKey::from_bytes(&ltpk)?
-            {
-                return Err(tlv::Error::Unknown);
-            }
-            pairing.permissions = permissions;
-            s.save_pairing(&pairing).await?;
-
-            drop(s);
-
-            event_emitter
-                .lock()
-                .await
-                .emit(&Event::ControllerPaired { id: pairing.id })
-                .await;
-        },
-        Err(_) => {
-            if let Some(max_peers) = config.lock().await.max_peers {
-                if s.count_pairings().await? + 1 > max_peers {
-                    return Err(tlv::Error::MaxPeers);
-                }
-            }
-
-            let mut public_key = [0; 32];
-            public_key.clone_from_slice(&ltpk);
-            let pairing = Pairing {
-                id: pairing_uuid,
-                permissions,
-                public_key,
-            };
-            s.save_pairing(&pairing).await?;
-
-            drop(s);
-
-            event_emitter
-                .lock()
-                .await
-                .emit(&Event::ControllerPaired { id: pairing.id })
-                .await;
-        },
-    }
+    pairing::add_pairing(&config, &storage, &event_emitter, pairing_uuid, &ltpk, permissions).await?;
 
     info!("pairings M2: sending add pairing response");
 
@@ -224,15 +180,8 @@ async fn handle_remove(
 
     let uuid_str = str::from_utf8(&pairing_id)?;
     let pairing_uuid = Uuid::parse_str(uuid_str)?;
-    // let pairing_id = storage.lock().await.load_pairing(&pairing_uuid).await?.id;
-    // storage.lock().await.delete_pairing(&pairing_id).await?;
-    storage.lock().await.delete_pairing(&pairing_uuid).await?;
 
-    event_emitter
-        .lock()
-        .await
-        .emit(&Event::ControllerUnpaired { id: pairing_uuid })
-        .await;
+    pairing::remove_pairing(&storage, &event_emitter, &pairing_uuid).await?;
 
     info!("pairings M2: sending remove pairing response");
 
@@ -7,7 +7,7 @@ use hyper::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    characteristic::{Format, Perm, Unit},
+    characteristic::{CallbackError, Format, Perm, Unit},
     Error,
     HapType,
     Result,
@@ -33,6 +33,20 @@ pub enum Status {
     InvalidValueInRequest = -70410,
 }
 
+impl From<&Error> for Status {
+    /// Returns the HAP status reported to a controller for a failed read or write of a Characteristic.
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Callback(CallbackError::ServiceCommunicationFailure) => Status::ServiceCommunicationFailure,
+            Error::Callback(CallbackError::ResourceBusy) => Status::ResourceBusy,
+            Error::Callback(CallbackError::InsufficientPrivileges) => Status::InsufficientPrivileges,
+            Error::Callback(CallbackError::InvalidValueInRequest) | Error::InvalidValue(_) =>
+                Status::InvalidValueInRequest,
+            _ => Status::ServiceCommunicationFailure,
+        }
+    }
+}
+
 #[derive(Debug)]
 enum ContentType {
     PairingTLV8,
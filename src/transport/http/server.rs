@@ -1,4 +1,5 @@
 use std::{
+    net::SocketAddr,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
@@ -6,12 +7,12 @@ use std::{
 
 use futures::{
     channel::oneshot,
-    future::{self, BoxFuture, Future, FutureExt, TryFutureExt},
+    future::{self, BoxFuture, Either, Future, FutureExt, TryFutureExt},
     lock::Mutex,
     stream::StreamExt,
 };
 use hyper::{server::conn::Http, service::Service, Body, Method, Request, Response, StatusCode};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use tokio::net::TcpListener;
 
 use crate::{
@@ -69,6 +70,7 @@ impl Api {
         accessory_list: pointer::AccessoryList,
         event_emitter: pointer::EventEmitter,
         session_sender: oneshot::Sender<Session>,
+        peer_addr: SocketAddr,
     ) -> Self {
         Api {
             controller_id,
@@ -79,7 +81,10 @@ impl Api {
             event_emitter,
             handlers: Handlers {
                 pair_setup: Arc::new(Mutex::new(Box::new(TlvHandler::from(PairSetup::new())))),
-                pair_verify: Arc::new(Mutex::new(Box::new(TlvHandler::from(PairVerify::new(session_sender))))),
+                pair_verify: Arc::new(Mutex::new(Box::new(TlvHandler::from(PairVerify::new(
+                    session_sender,
+                    peer_addr,
+                ))))),
                 accessories: Arc::new(Mutex::new(Box::new(JsonHandler::from(Accessories::new())))),
                 get_characteristics: Arc::new(Mutex::new(Box::new(JsonHandler::from(GetCharacteristics::new())))),
                 put_characteristics: Arc::new(Mutex::new(Box::new(JsonHandler::from(UpdateCharacteristics::new())))),
@@ -154,6 +159,7 @@ pub struct Server {
     storage: pointer::Storage,
     accessory_list: pointer::AccessoryList,
     event_emitter: pointer::EventEmitter,
+    session_registry: pointer::SessionRegistry,
 }
 
 impl Server {
@@ -162,12 +168,14 @@ impl Server {
         storage: pointer::Storage,
         accessory_list: pointer::AccessoryList,
         event_emitter: pointer::EventEmitter,
+        session_registry: pointer::SessionRegistry,
     ) -> Self {
         Server {
             config,
             storage,
             accessory_list,
             event_emitter,
+            session_registry,
         }
     }
 
@@ -176,6 +184,7 @@ impl Server {
         let storage = self.storage.clone();
         let accessory_list = self.accessory_list.clone();
         let event_emitter = self.event_emitter.clone();
+        let session_registry = self.session_registry.clone();
 
         async move {
             let socket_addr = config.lock().await.socket_addr;
@@ -188,7 +197,9 @@ impl Server {
             while let Some(stream) = incoming.next().await {
                 let stream = stream?;
 
-                debug!("incoming TCP stream from {}", stream.peer_addr()?);
+                let peer_addr = stream.peer_addr()?;
+
+                debug!("incoming TCP stream from {}", peer_addr);
 
                 let (
                     encrypted_stream,
@@ -198,26 +209,47 @@ impl Server {
                     incoming_waker,
                     outgoing_waker,
                 ) = EncryptedStream::new(stream);
-                let stream_wrapper =
-                    StreamWrapper::new(stream_incoming, stream_outgoing.clone(), incoming_waker, outgoing_waker);
+                let controller_id = encrypted_stream.controller_id.clone();
                 let event_subscriptions = Arc::new(Mutex::new(vec![]));
 
+                let (session_id, shutdown_receiver) = match session_registry.lock().await.register(
+                    peer_addr,
+                    controller_id.clone(),
+                    event_subscriptions.clone(),
+                ) {
+                    Ok(session) => session,
+                    Err(e) => {
+                        warn!("rejecting TCP stream from {}: {}", peer_addr, e);
+                        continue;
+                    },
+                };
+
+                let stream_wrapper = StreamWrapper::new(
+                    stream_incoming,
+                    stream_outgoing.clone(),
+                    incoming_waker,
+                    outgoing_waker.clone(),
+                );
+
                 let api = Api::new(
-                    encrypted_stream.controller_id.clone(),
+                    controller_id.clone(),
                     event_subscriptions.clone(),
                     config.clone(),
                     storage.clone(),
                     accessory_list.clone(),
                     event_emitter.clone(),
                     session_sender,
+                    peer_addr,
                 );
 
-                event_emitter.lock().await.add_listener(Box::new(move |event| {
+                let listener_id = event_emitter.lock().await.add_listener(Box::new(move |event| {
                     let event_subscriptions_ = event_subscriptions.clone();
                     let stream_outgoing_ = stream_outgoing.clone();
                     async move {
                         match *event {
-                            Event::CharacteristicValueChanged { aid, iid, ref value } => {
+                            Event::CharacteristicValueChanged {
+                                aid, iid, ref value, ..
+                            } => {
                                 let mut dropped_subscriptions = vec![];
                                 for (i, &(s_aid, s_iid)) in event_subscriptions_.lock().await.iter().enumerate() {
                                     if s_aid == aid && s_iid == iid {
@@ -238,31 +270,52 @@ impl Server {
                                     ev.remove(s);
                                 }
                             },
+                            Event::AccessoryRemoved { aid } => {
+                                event_subscriptions_.lock().await.retain(|&(s_aid, _)| s_aid != aid);
+                            },
                             _ => {},
                         }
                     }
                     .boxed()
                 }));
 
-                let http = Http::new();
-
-                // futures::try_join!(
-                //     encrypted_stream.map_err(|e| {
-                //         error!("{:?}", e);
-                //         Error::from(e)
-                //     }),
-                //     http.serve_connection(stream_wrapper, api).map_err(|e| {
-                //         error!("{:?}", e);
-                //         Error::from(e)
-                //     }),
-                // )?;
-
-                futures::join!(
-                    encrypted_stream.map_err(|e| error!("{:?}", e)).map(|_| ()),
-                    http.serve_connection(stream_wrapper, api)
-                        .map_err(|e| error!("{:?}", e))
-                        .map(|_| ()),
-                );
+                let event_emitter = event_emitter.clone();
+                let session_registry = session_registry.clone();
+
+                tokio::spawn(async move {
+                    let serve = async {
+                        let connection = Http::new().serve_connection(stream_wrapper, api);
+                        futures::pin_mut!(connection);
+
+                        // on a disconnect request, the request currently being handled is answered before closing
+                        let res = match future::select(connection.as_mut(), shutdown_receiver).await {
+                            Either::Left((res, _)) => res,
+                            Either::Right(_) => {
+                                connection.as_mut().graceful_shutdown();
+                                connection.await
+                            },
+                        };
+                        if let Err(e) = res {
+                            error!("{:?}", e);
+                        }
+
+                        // dropping the last sender of the outgoing stream lets the encrypted stream close
+                        event_emitter.lock().await.remove_listener(listener_id);
+                        if let Some(waker) = outgoing_waker.lock().expect("accessing outgoing_waker").take() {
+                            waker.wake()
+                        }
+                    };
+
+                    futures::join!(encrypted_stream.map_err(|e| error!("{:?}", e)).map(|_| ()), serve);
+
+                    session_registry.lock().await.unregister(session_id);
+
+                    let controller_id = *controller_id.read().expect("reading controller_id");
+                    event_emitter.lock().await.emit(&Event::SessionClosed {
+                        controller_id,
+                        peer_addr,
+                    });
+                });
             }
 
             Ok(())
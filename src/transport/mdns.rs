@@ -1,8 +1,8 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use futures::future::Future;
 use log::debug;
-use tokio::time;
+use tokio::{sync::Notify, time};
 
 use crate::pointer;
 
@@ -10,11 +10,21 @@ use crate::pointer;
 #[derive(Debug, Clone)]
 pub struct MdnsResponder {
     config: pointer::Config,
+    update: Arc<Notify>,
 }
 
 impl MdnsResponder {
     /// Creates a new mDNS Responder.
-    pub fn new(config: pointer::Config) -> Self { MdnsResponder { config } }
+    pub fn new(config: pointer::Config) -> Self {
+        MdnsResponder {
+            config,
+            update: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Triggers an immediate re-announcement of the TXT records, e.g. after the configuration number or the status flag
+    /// changed.
+    pub fn update_records(&self) { self.update.notify(); }
 
     // this should be the correct implementation, but (as of 0.4.1) the UDP stream implementation of libmdns is
     // broken. instead of polling & waking correctly, the stream is busy looping on Poll::Pending and needs to be
@@ -60,6 +70,7 @@ impl MdnsResponder {
     /// Returns a Future handle to the mDNS responder operation that can be passed to an executor.
     pub fn run_handle(&self) -> impl Future<Output = ()> + Send + '_ {
         let config = self.config.clone();
+        let update = self.update.clone();
         std::thread::spawn(move || {
             let mut rt = tokio::runtime::Runtime::new().expect("creating tokio runtime");
             rt.block_on(async move {
@@ -82,11 +93,13 @@ impl MdnsResponder {
                     ]);
                     debug!("announcing mDNS: {:?}", &tr);
 
-                    time::delay_for(Duration::from_millis(match status_flag {
-                        crate::transport::bonjour::BonjourStatusFlag::NotPaired => 1000,
-                        _ => 20_000,
-                    }))
-                    .await;
+                    tokio::select! {
+                        _ = time::delay_for(Duration::from_millis(match status_flag {
+                            crate::transport::bonjour::BonjourStatusFlag::NotPaired => 1000,
+                            _ => 20_000,
+                        })) => {},
+                        _ = update.notified() => {},
+                    }
                 }
 
                 Ok(()) as Result<(), ()>
@@ -417,7 +417,10 @@ impl Future for EncryptedStream {
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let encrypted_stream = Pin::into_inner(self);
-        EncryptedStream::poll_outgoing(Pin::new(encrypted_stream), cx)?;
+        // the stream is closed once all outgoing data is written and the HTTP connection is gone
+        if EncryptedStream::poll_outgoing(Pin::new(encrypted_stream), cx)?.is_ready() {
+            return Poll::Ready(Ok(()));
+        }
         EncryptedStream::poll_incoming(Pin::new(encrypted_stream), cx)
     }
 }